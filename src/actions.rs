@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::blur_result::ImageReport;
+use crate::config::ActionConfig;
+use crate::journal::{Journal, JournalEntry};
+
+/// What happens to a matching file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Move,
+    Copy,
+    HardLink,
+    SymLink,
+    Trash,
+}
+
+impl ActionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionKind::Move => "move",
+            ActionKind::Copy => "copy",
+            ActionKind::HardLink => "hardlink",
+            ActionKind::SymLink => "symlink",
+            ActionKind::Trash => "trash",
+        }
+    }
+}
+
+/// Link flavor for `--link-to`.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    Hard,
+    Symbolic,
+}
+
+/// One file operation, planned or carried out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub kind: ActionKind,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// Applies the configured action to each image (and its XMP sidecars) as the run goes. Destinations
+/// mirror the source's path relative to the base directory; taken names get a `-N` suffix. Every
+/// operation is appended to the journal, with the image's scores, before the next one starts.
+pub struct FileActions {
+    kind: ActionKind,
+    /// Destination of selected images; `None` for trash
+    dest: Option<PathBuf>,
+    /// Destination of the images that were not selected, if any
+    others: Option<PathBuf>,
+    base: PathBuf,
+    dry_run: bool,
+    journal: Option<Journal>,
+    /// Destinations handed out in this run, so a dry run resolves collisions the same way
+    claimed: HashSet<PathBuf>,
+}
+
+impl FileActions {
+    /// The configured action, or `None` if no action is configured.
+    pub fn from_config(config: &ActionConfig) -> Result<Option<Self>, String> {
+        let link_kind = match config.link_kind.unwrap_or(LinkKind::Hard) {
+            LinkKind::Hard => ActionKind::HardLink,
+            LinkKind::Symbolic => ActionKind::SymLink,
+        };
+        let mut chosen = Vec::new();
+        for (kind, dest) in [(ActionKind::Move, &config.move_to), (ActionKind::Copy, &config.copy_to), (link_kind, &config.link_to)] {
+            if let Some(dest) = dest {
+                chosen.push((kind, Some(PathBuf::from(dest))));
+            }
+        }
+        if config.trash.unwrap_or(false) {
+            chosen.push((ActionKind::Trash, None));
+        }
+        let (kind, dest) = match chosen.len() {
+            0 if config.others_to.is_some() => return Err("--others-to needs --move-to, --copy-to or --link-to".to_string()),
+            0 => return Ok(None),
+            1 => chosen.remove(0),
+            _ => return Err("Only one of --move-to, --copy-to, --link-to and --trash can be used".to_string()),
+        };
+        if kind == ActionKind::Trash && config.others_to.is_some() {
+            return Err("--others-to cannot be combined with --trash".to_string());
+        }
+        let cwd = std::env::current_dir().map_err(|e| format!("Failed to read the current directory: {}", e))?;
+        let base = config.base.as_ref().map_or(cwd.clone(), |b| normalize(&cwd.join(b)));
+        let dry_run = config.dry_run.unwrap_or(false);
+        let journal = if dry_run {
+            None
+        } else {
+            Some(Journal::open(Path::new(config.journal.as_deref().unwrap_or("grepfuzz-journal.jsonl")))?)
+        };
+        Ok(Some(Self {
+            kind,
+            dest,
+            others: config.others_to.as_ref().map(PathBuf::from),
+            base,
+            dry_run,
+            journal,
+            claimed: HashSet::new(),
+        }))
+    }
+
+    /// Applies the action to `path` if it was selected, or to the others directory if configured.
+    /// Returns the operations performed (or, in a dry run, planned): the image first, then its sidecars.
+    pub fn apply(&mut self, path: &Path, report: &ImageReport, selected: bool) -> Result<Vec<Operation>, String> {
+        let dest_root = match (selected, self.kind) {
+            (true, ActionKind::Trash) => None,
+            (true, _) => self.dest.clone(),
+            (false, _) => match self.others {
+                Some(ref others) => Some(others.clone()),
+                None => return Ok(Vec::new()),
+            },
+        };
+        let mut ops = Vec::new();
+        let image_dest = match dest_root {
+            Some(ref root) => self.claim(unique_destination(&root.join(relative_destination(path, &self.base)), &self.claimed)),
+            // The trash picks its own names; the operation records where the file went afterwards
+            None => PathBuf::new(),
+        };
+        ops.push(Operation { kind: self.kind, source: path.to_path_buf(), destination: image_dest.clone() });
+        for sidecar in sidecars(path) {
+            let destination = match dest_root {
+                Some(_) => self.claim(unique_destination(&sidecar_destination(path, &sidecar, &image_dest), &self.claimed)),
+                None => PathBuf::new(),
+            };
+            ops.push(Operation { kind: self.kind, source: sidecar, destination });
+        }
+        if self.dry_run {
+            for op in &ops {
+                let to = if op.kind == ActionKind::Trash { "trash".to_string() } else { op.destination.display().to_string() };
+                eprintln!("would {} {} -> {}", op.kind.name(), op.source.display(), to);
+            }
+            return Ok(ops);
+        }
+        let mut done = Vec::new();
+        for mut op in ops {
+            op.destination = perform(&op)?;
+            if let Some(ref mut journal) = self.journal {
+                journal.append(&JournalEntry::new(&op, Some(report)))?;
+            }
+            done.push(op);
+        }
+        Ok(done)
+    }
+
+    /// Directories files are moved, copied or linked into.
+    pub fn destinations(&self) -> Vec<PathBuf> {
+        self.dest.iter().chain(self.others.iter()).cloned().collect()
+    }
+
+    fn claim(&mut self, dest: PathBuf) -> PathBuf {
+        self.claimed.insert(dest.clone());
+        dest
+    }
+}
+
+/// Carries out one operation and returns where the file ended up.
+pub fn perform(op: &Operation) -> Result<PathBuf, String> {
+    let (src, dst) = (&op.source, &op.destination);
+    let err = |e: io::Error| format!("Failed to {} {} to {}: {}", op.kind.name(), src.display(), dst.display(), e);
+    if op.kind == ActionKind::Trash {
+        return crate::trash::trash(src).map(|t| t.file);
+    }
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).map_err(err)?;
+    }
+    match op.kind {
+        ActionKind::Move => match rename_new(src, dst) {
+            // Across filesystems: copy, then remove the original
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                copy_new(src, dst).map_err(err)?;
+                fs::remove_file(src).map_err(err)?;
+            }
+            other => other.map_err(err)?,
+        },
+        ActionKind::Copy => copy_new(src, dst).map_err(err)?,
+        ActionKind::HardLink => fs::hard_link(src, dst).map_err(err)?,
+        ActionKind::SymLink => {
+            let target = fs::canonicalize(src).map_err(err)?;
+            symlink(&target, dst).map_err(err)?;
+        }
+        ActionKind::Trash => unreachable!(),
+    }
+    Ok(dst.clone())
+}
+
+/// Renames without ever overwriting an existing destination: `renameat2` with `RENAME_NOREPLACE`
+/// on Linux, a hard link and unlink elsewhere and on filesystems that do not support it.
+#[cfg(target_os = "linux")]
+fn rename_new(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = |p: &Path| std::ffi::CString::new(p.as_os_str().as_bytes()).map_err(io::Error::other);
+    let (from, to) = (c_path(src)?, c_path(dst)?);
+    let ret = unsafe { libc::renameat2(libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE) };
+    if ret == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    if !matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) {
+        return Err(e);
+    }
+    link_and_unlink(src, dst)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_new(src: &Path, dst: &Path) -> io::Result<()> {
+    link_and_unlink(src, dst)
+}
+
+/// Moves by hard-linking, which fails rather than replace an existing destination, then unlinking.
+fn link_and_unlink(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::hard_link(src, dst)?;
+    fs::remove_file(src)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Copies without ever overwriting an existing destination.
+fn copy_new(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut from = File::open(src)?;
+    let mut to = OpenOptions::new().write(true).create_new(true).open(dst)?;
+    io::copy(&mut from, &mut to)?;
+    to.set_permissions(from.metadata()?.permissions())?;
+    to.sync_all()
+}
+
+/// Path of `path` below a destination root: relative to `base` when inside it, otherwise the whole
+/// absolute path without its root. `.` and `..` are resolved lexically, so nothing lands outside the root.
+pub fn relative_destination(path: &Path, base: &Path) -> PathBuf {
+    let abs = normalize(&base.join(path));
+    let rel = abs.strip_prefix(base).unwrap_or(&abs);
+    rel.components().filter(|c| matches!(c, Component::Normal(_))).collect()
+}
+
+/// `dest`, or the first free `stem-N.ext` next to it.
+pub fn unique_destination(dest: &Path, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let taken = |p: &Path| claimed.contains(p) || fs::symlink_metadata(p).is_ok();
+    if !taken(dest) {
+        return dest.to_path_buf();
+    }
+    let stem = dest.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = dest.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| dest.with_file_name(format!("{}-{}{}", stem, n, ext)))
+        .find(|p| !taken(p))
+        .expect("unbounded range")
+}
+
+/// Existing XMP sidecars of an image, in both naming conventions.
+fn sidecars(image: &Path) -> Vec<PathBuf> {
+    let mut appended = image.as_os_str().to_owned();
+    appended.push(".xmp");
+    let replaced = image.with_extension("xmp");
+    [PathBuf::from(appended), replaced].into_iter().filter(|p| p != image && p.is_file()).collect()
+}
+
+/// Sidecar name matching the image's (possibly renamed) destination, in the sidecar's own convention.
+fn sidecar_destination(image: &Path, sidecar: &Path, image_dest: &Path) -> PathBuf {
+    let mut appended = image.as_os_str().to_owned();
+    appended.push(".xmp");
+    if sidecar == Path::new(&appended) {
+        let mut dest = image_dest.as_os_str().to_owned();
+        dest.push(".xmp");
+        PathBuf::from(dest)
+    } else {
+        image_dest.with_extension("xmp")
+    }
+}
+
+/// Resolves `.` and `..` lexically.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations_mirror_the_tree() {
+        let base = Path::new("/shoot");
+        assert_eq!(relative_destination(Path::new("./day1/a.jpg"), base), PathBuf::from("day1/a.jpg"));
+        assert_eq!(relative_destination(Path::new("/shoot/day2/b.jpg"), base), PathBuf::from("day2/b.jpg"));
+        assert_eq!(relative_destination(Path::new("/elsewhere/c.jpg"), base), PathBuf::from("elsewhere/c.jpg"));
+        assert_eq!(relative_destination(Path::new("../../d.jpg"), base), PathBuf::from("d.jpg"));
+    }
+
+    #[test]
+    fn moves_with_sidecars_and_resolves_collisions() {
+        let root = std::env::temp_dir().join(format!("grepfuzz-actions-{}", std::process::id()));
+        let (src, reject) = (root.join("card"), root.join("reject"));
+        fs::create_dir_all(src.join("day1")).unwrap();
+        fs::create_dir_all(reject.join("day1")).unwrap();
+        fs::write(src.join("day1/a.jpg"), b"a").unwrap();
+        fs::write(src.join("day1/a.jpg.xmp"), b"<x/>").unwrap();
+        fs::write(reject.join("day1/a.jpg"), b"older").unwrap();
+        let config = ActionConfig {
+            move_to: Some(reject.display().to_string()),
+            base: Some(src.display().to_string()),
+            journal: Some(root.join("journal.jsonl").display().to_string()),
+            ..ActionConfig::default()
+        };
+        let report = ImageReport::default();
+        let mut actions = FileActions::from_config(&config).unwrap().unwrap();
+        assert!(actions.apply(&src.join("day1/a.jpg"), &report, false).unwrap().is_empty());
+        let ops = actions.apply(&src.join("day1/a.jpg"), &report, true).unwrap();
+        assert_eq!(ops[0].destination, reject.join("day1/a-1.jpg"));
+        assert_eq!(ops[1].destination, reject.join("day1/a-1.jpg.xmp"));
+        assert_eq!(fs::read(reject.join("day1/a-1.jpg")).unwrap(), b"a");
+        assert!(!src.join("day1/a.jpg").exists() && !src.join("day1/a.jpg.xmp").exists());
+        let journal = crate::journal::read_journal(&root.join("journal.jsonl")).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert_eq!((journal[0].action, journal[0].size), (ActionKind::Move, Some(1)));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn moves_never_overwrite() {
+        let root = std::env::temp_dir().join(format!("grepfuzz-actions-noreplace-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let (src, dst) = (root.join("a.jpg"), root.join("b.jpg"));
+        fs::write(&src, b"new").unwrap();
+        fs::write(&dst, b"taken").unwrap();
+        let op = Operation { kind: ActionKind::Move, source: src.clone(), destination: dst.clone() };
+        assert!(perform(&op).is_err());
+        assert_eq!((fs::read(&src).unwrap(), fs::read(&dst).unwrap()), (b"new".to_vec(), b"taken".to_vec()));
+        fs::remove_file(&dst).unwrap();
+        perform(&op).unwrap();
+        assert!(!src.exists() && fs::read(&dst).unwrap() == b"new");
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -1,6 +1,62 @@
+use std::path::PathBuf;
+use crate::burst::BurstMembership;
+use crate::dedup::DuplicateMembership;
+use crate::exposure::ExposureMetrics;
+use crate::frames::FrameReport;
+use crate::metadata::ImageMetadata;
+use crate::noise::NoiseReport;
+use crate::raw_preview::RawPreview;
+use crate::relative::RelativeMembership;
+use crate::roi::RoiReport;
+use crate::tile_analysis::TileSummary;
+
 pub struct BlurResult {
     pub name: String,
     pub value: f64,
     pub threshold: f64,
     pub is_blurry: bool,
+    /// Whether the value grows with blur (ShakeRisk) instead of shrinking (every pixel detector)
+    pub higher_is_blurrier: bool,
+}
+
+/// Everything collected for one analyzed image.
+#[derive(Default)]
+pub struct ImageReport {
+    pub is_blurry: bool,
+    pub results: Vec<BlurResult>,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub focal: Option<String>,
+    /// EXIF metadata; empty for in-memory images.
+    pub metadata: ImageMetadata,
+    /// EXIF orientation (1-8) applied on load; width, height and all coordinates are upright.
+    pub orientation: u8,
+    /// Embedded JPEG preview analyzed in place of a camera RAW file.
+    pub preview: Option<RawPreview>,
+    /// Per-frame results of a multi-frame file, frame 0 included; empty unless frame analysis is
+    /// enabled and the file has more than one frame.
+    pub frames: Vec<FrameReport>,
+    /// Per-detector tile summaries; empty unless tiled analysis is enabled.
+    pub tiles: Vec<TileSummary>,
+    /// Per-region results; empty unless regions of interest are configured.
+    pub rois: Vec<RoiReport>,
+    /// Noise estimate; `None` unless noise estimation is enabled.
+    pub noise: Option<NoiseReport>,
+    /// Exposure of the image as captured; `None` unless exposure metrics are enabled.
+    pub exposure: Option<ExposureMetrics>,
+    /// Path of the exported heatmap, if one was written.
+    pub heatmap: Option<PathBuf>,
+    /// Burst grouping and rank; set only in burst mode, once the whole input has been analyzed.
+    pub burst: Option<BurstMembership>,
+    /// Perceptual hash of the grayscale image; `None` unless duplicate detection is enabled.
+    pub hash: Option<u64>,
+    /// Near-duplicate cluster; set only in dedup mode, once the whole input has been analyzed.
+    pub duplicate: Option<DuplicateMembership>,
+    /// Standing within its group; set only in relative mode, once the whole input has been analyzed.
+    pub relative: Option<RelativeMembership>,
+    /// Blur probability from the trained combiner; `None` unless a combiner model is configured.
+    pub blur_probability: Option<f64>,
+    /// JPEG thumbnail data URI made from the decoded image; `None` unless an HTML report is configured.
+    pub thumbnail: Option<String>,
 }
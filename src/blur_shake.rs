@@ -0,0 +1,91 @@
+use image::{ImageBuffer, Luma};
+use crate::BlurDetector;
+use crate::metadata::ImageMetadata;
+
+/// Pseudo-detector predicting handheld camera shake from EXIF, using the reciprocal rule.
+///
+/// The slowest safe handheld shutter speed is `1 / focal_35mm` seconds, extended by a factor of
+/// two per stop of stabilization. The value is the exposure time divided by that limit, so 1.0
+/// sits exactly on the rule and 4.0 is two stops slower than it. Unlike the pixel detectors,
+/// higher values mean blurrier, and the image is flagged when the value exceeds `threshold`.
+pub struct ShakeRiskDetector {
+    pub threshold: f64,
+    pub exposure_time: f64,
+    pub focal_length_35mm: f64,
+    pub stabilization_stops: f64,
+}
+
+impl ShakeRiskDetector {
+    pub fn new(threshold: f64, exposure_time: f64, focal_length_35mm: f64, stabilization_stops: f64) -> Self {
+        Self { threshold, exposure_time, focal_length_35mm, stabilization_stops }
+    }
+
+    /// Builds the detector from EXIF. Falls back to `focal_length * crop_factor` when the
+    /// 35mm-equivalent focal length is missing; returns `None` without exposure or focal length.
+    pub fn from_metadata(meta: &ImageMetadata, threshold: f64, stabilization_stops: f64, crop_factor: f64) -> Option<Self> {
+        let exposure_time = meta.exposure_time.filter(|&t| t > 0.0)?;
+        let focal = meta.focal_length_35mm
+            .or_else(|| meta.focal_length.map(|f| f * crop_factor))
+            .filter(|&f| f > 0.0)?;
+        Some(Self::new(threshold, exposure_time, focal, stabilization_stops))
+    }
+
+    /// Slowest shutter speed, in seconds, the reciprocal rule allows handheld.
+    pub fn safe_exposure_time(&self) -> f64 {
+        2f64.powf(self.stabilization_stops) / self.focal_length_35mm
+    }
+
+    pub fn risk(&self) -> f64 {
+        self.exposure_time / self.safe_exposure_time()
+    }
+}
+
+impl BlurDetector for ShakeRiskDetector {
+    fn name(&self) -> &'static str {
+        "ShakeRisk"
+    }
+
+    fn detect(&self, _img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> (f64, bool) {
+        // Pixels are irrelevant: the prediction comes from the capture settings alone
+        let risk = self.risk();
+        (risk, risk > self.threshold)
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(exposure_time: Option<f64>, focal_length: Option<f64>, focal_length_35mm: Option<f64>) -> ImageMetadata {
+        ImageMetadata { exposure_time, focal_length, focal_length_35mm, ..ImageMetadata::default() }
+    }
+
+    #[test]
+    fn reciprocal_rule_with_stabilization_and_crop() {
+        // 1/50 s at 100 mm equivalent is one stop slower than the 1/100 s rule
+        let det = ShakeRiskDetector::from_metadata(&meta(Some(0.02), None, Some(100.0)), 1.0, 0.0, 1.0).unwrap();
+        assert!((det.risk() - 2.0).abs() < 1e-12);
+        assert!(det.detect(&ImageBuffer::new(0, 0)).1);
+        // Two stops of stabilization allow 1/25 s, so the same shot is safe
+        let stabilized = ShakeRiskDetector::from_metadata(&meta(Some(0.02), None, Some(100.0)), 1.0, 2.0, 1.0).unwrap();
+        assert!((stabilized.safe_exposure_time() - 0.04).abs() < 1e-12);
+        assert!(!stabilized.detect(&ImageBuffer::new(0, 0)).1);
+        // Without a 35mm value, 50 mm on a 1.5x crop body counts as 75 mm; the EXIF value wins when present
+        let cropped = ShakeRiskDetector::from_metadata(&meta(Some(1.0 / 75.0), Some(50.0), None), 1.0, 0.0, 1.5).unwrap();
+        assert_eq!(cropped.focal_length_35mm, 75.0);
+        assert!((cropped.risk() - 1.0).abs() < 1e-12);
+        let both = ShakeRiskDetector::from_metadata(&meta(Some(0.01), Some(50.0), Some(80.0)), 1.0, 0.0, 1.5).unwrap();
+        assert_eq!(both.focal_length_35mm, 80.0);
+    }
+
+    #[test]
+    fn missing_exif_gives_no_prediction() {
+        assert!(ShakeRiskDetector::from_metadata(&ImageMetadata::default(), 1.0, 0.0, 1.0).is_none());
+        assert!(ShakeRiskDetector::from_metadata(&meta(None, Some(50.0), Some(50.0)), 1.0, 0.0, 1.0).is_none());
+        assert!(ShakeRiskDetector::from_metadata(&meta(Some(0.01), None, None), 1.0, 0.0, 1.0).is_none());
+        assert!(ShakeRiskDetector::from_metadata(&meta(Some(0.0), Some(50.0), None), 1.0, 0.0, 1.0).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::blur_result::{BlurResult, ImageReport};
+
+/// Where an image landed in burst grouping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstMembership {
+    /// Zero-based burst number, in order of each burst's first appearance in the input.
+    pub id: usize,
+    /// Number of images in the burst.
+    pub size: usize,
+    /// 1 for the sharpest image of the burst.
+    pub rank: usize,
+    /// Combined sharpness score the ranking used.
+    pub score: f64,
+    /// True for the best `keep` images of the burst, false for rejects.
+    pub keep: bool,
+}
+
+/// Seconds since 1970-01-01 for an EXIF "YYYY:MM:DD HH:MM:SS" time (taken as UTC; only differences matter).
+pub fn parse_capture_time(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (date, time) = text.split_once([' ', 'T'])?;
+    let mut d = date.split([':', '-']).map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (d.next()??, d.next()??, d.next()??);
+    let mut t = time.split(':');
+    let hour = t.next()?.parse::<i64>().ok()?;
+    let minute = t.next()?.parse::<i64>().ok()?;
+    let second = t.next()?.parse::<f64>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days from civil date (proleptic Gregorian)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some((days * 86_400 + hour * 3_600 + minute * 60) as f64 + second)
+}
+
+/// One score for ranking images against each other: the mean of each detector's value relative to
+/// its threshold, so detectors on different scales weigh the same. Detectors whose value grows with
+/// blur are inverted. 1.0 means "exactly at the thresholds".
+pub fn combined_score(results: &[BlurResult]) -> f64 {
+    let ratios: Vec<f64> = results.iter()
+        .filter(|r| r.threshold > 0.0 && r.value.is_finite())
+        .map(|r| if r.higher_is_blurrier { r.threshold / r.value.max(1e-9) } else { r.value / r.threshold })
+        .collect();
+    if ratios.is_empty() {
+        0.0
+    } else {
+        ratios.iter().sum::<f64>() / ratios.len() as f64
+    }
+}
+
+/// Groups images into bursts and ranks each burst by sharpness.
+///
+/// Images from the same camera (by serial, falling back to make and model) whose capture times are
+/// at most `gap_seconds` apart, chained, form a burst. Images without a capture time are bursts of
+/// their own. Returns one membership per report, in input order.
+pub fn assign_bursts(reports: &[&ImageReport], gap_seconds: f64, keep: usize) -> Vec<BurstMembership> {
+    let camera = |r: &ImageReport| {
+        let m = &r.metadata;
+        m.serial_number.clone()
+            .or_else(|| m.camera_model.as_ref().map(|model| format!("{} {}", m.camera_make.as_deref().unwrap_or(""), model)))
+    };
+    let mut timed: Vec<(Option<String>, f64, usize)> = reports.iter().enumerate()
+        .filter_map(|(i, r)| Some((camera(r), parse_capture_time(r.metadata.capture_time.as_deref()?)?, i)))
+        .collect();
+    timed.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut grouped = vec![false; reports.len()];
+    for (k, (cam, time, i)) in timed.iter().enumerate() {
+        let continues = k > 0 && {
+            let (prev_cam, prev_time, _) = &timed[k - 1];
+            prev_cam == cam && time - prev_time <= gap_seconds
+        };
+        match groups.last_mut() {
+            Some(group) if continues => group.push(*i),
+            _ => groups.push(vec![*i]),
+        }
+        grouped[*i] = true;
+    }
+    groups.extend((0..reports.len()).filter(|&i| !grouped[i]).map(|i| vec![i]));
+    // Number bursts in input order
+    groups.sort_by_key(|g| g.iter().min().copied());
+
+    let scores: Vec<f64> = reports.iter().map(|r| combined_score(&r.results)).collect();
+    let mut memberships = vec![None; reports.len()];
+    for (id, mut group) in groups.into_iter().enumerate() {
+        group.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+        let size = group.len();
+        for (rank, i) in group.into_iter().enumerate() {
+            memberships[i] = Some(BurstMembership { id, size, rank: rank + 1, score: scores[i], keep: rank < keep });
+        }
+    }
+    memberships.into_iter().map(|m| m.expect("every image is in a burst")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ImageMetadata;
+
+    fn report(time: Option<&str>, serial: &str, value: f64) -> ImageReport {
+        ImageReport {
+            results: vec![BlurResult { name: "LaplacianVariance".to_string(), value, threshold: 1.0, is_blurry: value < 1.0, higher_is_blurrier: false }],
+            metadata: ImageMetadata {
+                capture_time: time.map(str::to_string),
+                serial_number: Some(serial.to_string()),
+                ..ImageMetadata::default()
+            },
+            ..ImageReport::default()
+        }
+    }
+
+    #[test]
+    fn combined_score_inverts_rising_detectors() {
+        let result = |value: f64, threshold: f64, higher_is_blurrier: bool| BlurResult {
+            name: "any".to_string(), value, threshold, is_blurry: false, higher_is_blurrier,
+        };
+        // Twice the pixel threshold, and half the allowed shake risk: both twice as good as the threshold
+        assert_eq!(combined_score(&[result(200.0, 100.0, false), result(0.5, 1.0, true)]), 2.0);
+        assert_eq!(combined_score(&[]), 0.0);
+    }
+
+    #[test]
+    fn parses_exif_times() {
+        assert_eq!(parse_capture_time("1970:01:02 00:00:01"), Some(86_401.0));
+        let a = parse_capture_time("2024:02:28 23:59:59").unwrap();
+        let b = parse_capture_time("2024:03:01 00:00:00").unwrap();
+        assert_eq!(b - a, 86_401.0);
+        assert_eq!(parse_capture_time("not a time"), None);
+    }
+
+    #[test]
+    fn groups_by_gap_and_camera() {
+        let reports = [
+            report(Some("2024:05:01 10:00:00"), "A", 2.0),
+            report(Some("2024:05:01 10:00:01"), "A", 5.0),
+            report(Some("2024:05:01 10:00:01"), "B", 1.0),
+            report(Some("2024:05:01 10:00:02"), "A", 3.0),
+            report(Some("2024:05:01 10:00:30"), "A", 1.0),
+            report(None, "A", 1.0),
+        ];
+        let refs: Vec<&ImageReport> = reports.iter().collect();
+        let bursts = assign_bursts(&refs, 2.0, 1);
+        let ids: Vec<usize> = bursts.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![0, 0, 1, 0, 2, 3]);
+        let ranks: Vec<usize> = bursts.iter().map(|b| b.rank).collect();
+        assert_eq!(ranks, vec![3, 1, 1, 2, 1, 1]);
+        assert!(bursts[1].keep && !bursts[0].keep && !bursts[3].keep);
+    }
+}
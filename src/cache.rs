@@ -0,0 +1,285 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use crate::metadata::ImageMetadata;
+use crate::raw_preview::RawPreview;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS images (
+        path TEXT PRIMARY KEY,
+        size INTEGER NOT NULL,
+        mtime_ns INTEGER NOT NULL,
+        content_hash TEXT,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL,
+        orientation INTEGER NOT NULL,
+        preview TEXT,
+        metadata TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS scores (
+        path TEXT NOT NULL,
+        detector TEXT NOT NULL,
+        pipeline TEXT NOT NULL,
+        value REAL NOT NULL,
+        PRIMARY KEY (path, detector, pipeline)
+    );
+    CREATE TABLE IF NOT EXISTS hashes (
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        hash INTEGER NOT NULL,
+        PRIMARY KEY (path, kind)
+    );
+";
+
+/// Identity of a file on disk. A cache entry is valid while size and mtime are unchanged, or, with
+/// content hashing on, while size and content are (so touched or copied files stay cached).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileKey {
+    pub size: u64,
+    pub mtime_ns: i64,
+    pub content_hash: Option<String>,
+}
+
+impl FileKey {
+    /// Size and mtime of `path`, without a content hash.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let meta = std::fs::metadata(path).map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?;
+        let mtime_ns = meta.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as i64);
+        Ok(Self { size: meta.len(), mtime_ns, content_hash: None })
+    }
+
+    fn hash_content(&mut self, path: &Path) -> Result<(), String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        self.content_hash = Some(fnv1a64(&data));
+        Ok(())
+    }
+}
+
+fn fnv1a64(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in data {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Everything needed to rebuild a report without decoding: raw detector values (never verdicts,
+/// so thresholds can change), dimensions, EXIF and, in dedup mode, the perceptual hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedImage {
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u8,
+    pub preview: Option<RawPreview>,
+    pub metadata: ImageMetadata,
+    /// Raw value per detector name.
+    pub scores: Vec<(String, f64)>,
+    /// Perceptual hash and the hash kind it was computed with.
+    pub hash: Option<(String, u64)>,
+}
+
+/// SQLite-backed store of analysis results.
+pub struct ResultCache {
+    conn: Connection,
+}
+
+impl ResultCache {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open cache {}: {}", path.display(), e))?;
+        // WAL keeps per-image commits cheap
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .map_err(|e| format!("Failed to initialize cache {}: {}", path.display(), e))?;
+        Ok(Self { conn })
+    }
+
+    /// Current key of the file at `path`. With `content_hash`, an unchanged file reuses the stored hash
+    /// and the file is only read when it will be analyzed anyway or its mtime alone moved.
+    pub fn file_key(&self, path: &Path, content_hash: bool) -> Result<FileKey, String> {
+        let mut key = FileKey::read(path)?;
+        if !content_hash {
+            return Ok(key);
+        }
+        let stored: Option<(i64, i64, Option<String>)> = self.conn.query_row(
+            "SELECT size, mtime_ns, content_hash FROM images WHERE path = ?1",
+            params![path.to_string_lossy()],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        ).optional().ok().flatten();
+        match stored {
+            Some((size, mtime_ns, Some(hash))) if size as u64 == key.size && mtime_ns == key.mtime_ns => key.content_hash = Some(hash),
+            _ => key.hash_content(path)?,
+        }
+        Ok(key)
+    }
+
+    /// Returns the cached entry for `path` if the file is unchanged and every detector in `detectors`
+    /// (and the hash kind, if given) has a value for `pipeline`.
+    pub fn lookup(&self, path: &Path, key: &FileKey, detectors: &[&str], pipeline: &str, hash_kind: Option<&str>) -> Option<CachedImage> {
+        let path_str = path.to_string_lossy();
+        let row = self.conn.query_row(
+            "SELECT size, mtime_ns, content_hash, width, height, orientation, preview, metadata FROM images WHERE path = ?1",
+            params![path_str],
+            |r| Ok((
+                r.get::<_, i64>(0)?, r.get::<_, i64>(1)?, r.get::<_, Option<String>>(2)?,
+                r.get::<_, u32>(3)?, r.get::<_, u32>(4)?, r.get::<_, u8>(5)?,
+                r.get::<_, Option<String>>(6)?, r.get::<_, String>(7)?,
+            )),
+        ).optional().ok()??;
+        let (size, mtime_ns, content_hash, width, height, orientation, preview, metadata) = row;
+        let stored = FileKey { size: size as u64, mtime_ns, content_hash };
+        if !Self::same_file(&stored, key) {
+            return None;
+        }
+        if stored.mtime_ns != key.mtime_ns {
+            // Same content under a new mtime: remember the new one so the next run skips hashing
+            self.conn.execute("UPDATE images SET mtime_ns = ?2 WHERE path = ?1", params![path_str, key.mtime_ns]).ok();
+        }
+
+        let mut scores = Vec::new();
+        for &name in detectors {
+            let value: f64 = self.conn.query_row(
+                "SELECT value FROM scores WHERE path = ?1 AND detector = ?2 AND pipeline = ?3",
+                params![path_str, name, pipeline],
+                |r| r.get(0),
+            ).optional().ok()??;
+            scores.push((name.to_string(), value));
+        }
+        let hash = match hash_kind {
+            Some(kind) => {
+                let hash: i64 = self.conn.query_row(
+                    "SELECT hash FROM hashes WHERE path = ?1 AND kind = ?2",
+                    params![path_str, kind],
+                    |r| r.get(0),
+                ).optional().ok()??;
+                Some((kind.to_string(), hash as u64))
+            }
+            None => None,
+        };
+        Some(CachedImage {
+            width,
+            height,
+            orientation,
+            preview: preview.as_deref().and_then(parse_preview),
+            metadata: toml::from_str(&metadata).ok()?,
+            scores,
+            hash,
+        })
+    }
+
+    /// Stores `entry` for `path`. Values cached for other pipelines and hash kinds are kept while the
+    /// file is unchanged and dropped when it changed.
+    pub fn store(&self, path: &Path, key: &FileKey, entry: &CachedImage, pipeline: &str) -> Result<(), String> {
+        let path_str = path.to_string_lossy();
+        let err = |e: rusqlite::Error| format!("Failed to update cache for {}: {}", path.display(), e);
+        let metadata = toml::to_string(&entry.metadata).map_err(|e| format!("Failed to serialize metadata of {}: {}", path.display(), e))?;
+        let tx = self.conn.unchecked_transaction().map_err(err)?;
+        let stored = tx.query_row(
+            "SELECT size, mtime_ns, content_hash FROM images WHERE path = ?1",
+            params![path_str],
+            |r| Ok(FileKey { size: r.get::<_, i64>(0)? as u64, mtime_ns: r.get(1)?, content_hash: r.get(2)? }),
+        ).optional().map_err(err)?;
+        if stored.is_some_and(|s| !Self::same_file(&s, key)) {
+            tx.execute("DELETE FROM scores WHERE path = ?1", params![path_str]).map_err(err)?;
+            tx.execute("DELETE FROM hashes WHERE path = ?1", params![path_str]).map_err(err)?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO images (path, size, mtime_ns, content_hash, width, height, orientation, preview, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                path_str, key.size as i64, key.mtime_ns, key.content_hash, entry.width, entry.height, entry.orientation,
+                entry.preview.map(|p| format!("{},{},{},{}", p.offset, p.length, p.width, p.height)), metadata,
+            ],
+        ).map_err(err)?;
+        for (name, value) in &entry.scores {
+            tx.execute(
+                "INSERT OR REPLACE INTO scores (path, detector, pipeline, value) VALUES (?1, ?2, ?3, ?4)",
+                params![path_str, name, pipeline, value],
+            ).map_err(err)?;
+        }
+        if let Some((ref kind, hash)) = entry.hash {
+            tx.execute(
+                "INSERT OR REPLACE INTO hashes (path, kind, hash) VALUES (?1, ?2, ?3)",
+                params![path_str, kind, hash as i64],
+            ).map_err(err)?;
+        }
+        tx.commit().map_err(err)
+    }
+
+    fn same_file(stored: &FileKey, key: &FileKey) -> bool {
+        stored.size == key.size
+            && (stored.mtime_ns == key.mtime_ns
+                || (key.content_hash.is_some() && stored.content_hash == key.content_hash))
+    }
+}
+
+fn parse_preview(text: &str) -> Option<RawPreview> {
+    let mut parts = text.split(',').map(|p| p.parse::<u64>().ok());
+    let (offset, length, width, height) = (parts.next()??, parts.next()??, parts.next()??, parts.next()??);
+    Some(RawPreview { offset: offset as usize, length: length as usize, width: width as u32, height: height as u32 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_follow_file_identity() {
+        let db = std::env::temp_dir().join(format!("grepfuzz-cache-{}.sqlite", std::process::id()));
+        let cache = ResultCache::open(&db).unwrap();
+        let path = Path::new("/photos/a.jpg");
+        let key = FileKey { size: 10, mtime_ns: 1, content_hash: Some("abc".to_string()) };
+        let entry = CachedImage {
+            width: 4,
+            height: 3,
+            orientation: 6,
+            preview: None,
+            metadata: ImageMetadata { iso: Some(800), lens_model: Some("35mm".to_string()), ..ImageMetadata::default() },
+            scores: vec![("LaplacianVariance".to_string(), 0.25)],
+            hash: Some(("dhash".to_string(), u64::MAX)),
+        };
+        cache.store(path, &key, &entry, "normalize=none").unwrap();
+
+        assert_eq!(cache.lookup(path, &key, &["LaplacianVariance"], "normalize=none", Some("dhash")), Some(entry.clone()));
+        // Another pipeline or detector has no values yet
+        assert_eq!(cache.lookup(path, &key, &["LaplacianVariance"], "normalize=clahe", None), None);
+        assert_eq!(cache.lookup(path, &key, &["Tenengrad"], "normalize=none", None), None);
+        // A touched file with the same content stays cached; changed content does not
+        let touched = FileKey { mtime_ns: 2, ..key.clone() };
+        assert!(cache.lookup(path, &touched, &["LaplacianVariance"], "normalize=none", None).is_some());
+        let changed = FileKey { mtime_ns: 3, content_hash: Some("def".to_string()), ..key };
+        assert_eq!(cache.lookup(path, &changed, &["LaplacianVariance"], "normalize=none", None), None);
+
+        drop(cache);
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", db.display(), suffix)).ok();
+        }
+    }
+
+    #[test]
+    fn content_is_hashed_only_when_the_mtime_moved() {
+        let db = std::env::temp_dir().join(format!("grepfuzz-cache-key-{}.sqlite", std::process::id()));
+        let file = std::env::temp_dir().join(format!("grepfuzz-cache-key-{}.jpg", std::process::id()));
+        std::fs::write(&file, b"jpeg").unwrap();
+        let cache = ResultCache::open(&db).unwrap();
+        let key = cache.file_key(&file, true).unwrap();
+        assert!(key.content_hash.is_some());
+        assert_eq!(cache.file_key(&file, false).unwrap().content_hash, None);
+        let entry = CachedImage { width: 1, height: 1, orientation: 1, preview: None, metadata: ImageMetadata::default(), scores: vec![], hash: None };
+        // A stored hash for the same size and mtime is trusted without reading the file
+        let planted = FileKey { content_hash: Some("planted".to_string()), ..key.clone() };
+        cache.store(&file, &planted, &entry, "normalize=none").unwrap();
+        assert_eq!(cache.file_key(&file, true).unwrap(), planted);
+        let moved = FileKey { mtime_ns: key.mtime_ns + 1, ..planted };
+        cache.store(&file, &moved, &entry, "normalize=none").unwrap();
+        assert_eq!(cache.file_key(&file, true).unwrap(), key);
+
+        drop(cache);
+        std::fs::remove_file(&file).ok();
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", db.display(), suffix)).ok();
+        }
+    }
+}
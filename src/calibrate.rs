@@ -0,0 +1,304 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::cli::{CalibrateArgs, DatasetArgs};
+use crate::config::GrepfuzzConfig;
+use crate::detector_helpers::{build_detectors, detector_key};
+
+/// One labeled image. `blurry` is the positive class, since blurry images are what grepfuzz emits by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabeledImage {
+    pub path: PathBuf,
+    pub blurry: bool,
+}
+
+fn parse_label(label: &str) -> Option<bool> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "blurry" | "blur" | "1" | "true" | "yes" => Some(true),
+        "sharp" | "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads a `path,label` manifest (comma or tab separated; `#` comments and a header line are
+/// skipped). Labels are `blurry`/`sharp` or `1`/`0`; relative paths are resolved against the manifest's directory.
+pub fn load_manifest(path: &Path) -> Result<Vec<LabeledImage>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read manifest {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut images = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (file, label) = line.rsplit_once(['\t', ','])
+            .ok_or_else(|| format!("{}:{}: expected `path,label`", path.display(), n + 1))?;
+        let blurry = match parse_label(label) {
+            Some(b) => b,
+            None if n == 0 => continue, // header
+            None => return Err(format!("{}:{}: unknown label `{}` (use blurry or sharp)", path.display(), n + 1, label.trim())),
+        };
+        images.push(LabeledImage { path: base.join(file.trim()), blurry });
+    }
+    Ok(images)
+}
+
+/// Labels every file under `sharp_dir` sharp and every file under `blurry_dir` blurry, recursively, in sorted order.
+pub fn scan_labeled_dirs(sharp_dir: &Path, blurry_dir: &Path) -> Result<Vec<LabeledImage>, String> {
+    fn walk(dir: &Path, blurry: bool, out: &mut Vec<LabeledImage>) -> Result<(), String> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                walk(&path, blurry, out)?;
+            } else if !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) {
+                out.push(LabeledImage { path, blurry });
+            }
+        }
+        Ok(())
+    }
+    let mut images = Vec::new();
+    walk(sharp_dir, false, &mut images)?;
+    walk(blurry_dir, true, &mut images)?;
+    Ok(images)
+}
+
+/// Loads the labeled set from either a manifest or a pair of directories.
+pub fn load_dataset(args: &DatasetArgs) -> Result<Vec<LabeledImage>, String> {
+    match (args.labels.as_deref(), args.sharp_dir.as_deref(), args.blurry_dir.as_deref()) {
+        (Some(m), None, None) => load_manifest(Path::new(m)),
+        (None, Some(s), Some(b)) => scan_labeled_dirs(Path::new(s), Path::new(b)),
+        _ => Err("Give either a labels manifest or both --sharp-dir and --blurry-dir".to_string()),
+    }
+}
+
+/// Confusion counts for "blurry when value < threshold".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RocPoint {
+    pub threshold: f64,
+    pub tp: usize,
+    pub fp: usize,
+    pub tn: usize,
+    pub fn_: usize,
+}
+
+impl RocPoint {
+    pub fn precision(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fp)
+    }
+    pub fn recall(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fn_)
+    }
+    pub fn false_positive_rate(&self) -> f64 {
+        ratio(self.fp, self.fp + self.tn)
+    }
+    pub fn f1(&self) -> f64 {
+        ratio(2 * self.tp, 2 * self.tp + self.fp + self.fn_)
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 { 0.0 } else { a as f64 / b as f64 }
+}
+
+/// ROC curve over every useful threshold: below the smallest value, between each pair of distinct
+/// values, and above the largest. Points run from nothing flagged to everything flagged.
+pub fn roc_curve(samples: &[(f64, bool)]) -> Vec<RocPoint> {
+    let mut sorted: Vec<(f64, bool)> = samples.iter().copied().filter(|(v, _)| v.is_finite()).collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let positives = sorted.iter().filter(|(_, blurry)| *blurry).count();
+    let negatives = sorted.len() - positives;
+    let point = |threshold: f64, tp: usize, fp: usize| RocPoint { threshold, tp, fp, tn: negatives - fp, fn_: positives - tp };
+
+    let mut points = Vec::new();
+    let Some(&(first, _)) = sorted.first() else { return points };
+    points.push(point(first - first.abs().max(1e-9) * 0.01, 0, 0));
+    let (mut tp, mut fp) = (0, 0);
+    for (i, &(value, blurry)) in sorted.iter().enumerate() {
+        if blurry { tp += 1 } else { fp += 1 }
+        match sorted.get(i + 1) {
+            Some(&(next, _)) if next > value => points.push(point((value + next) / 2.0, tp, fp)),
+            Some(_) => {}
+            None => points.push(point(value + value.abs().max(1e-9) * 0.01, tp, fp)),
+        }
+    }
+    points
+}
+
+/// Area under the ROC curve (trapezoidal rule); 0.5 is chance, 1.0 a perfect separation.
+pub fn auc(points: &[RocPoint]) -> f64 {
+    points.windows(2)
+        .map(|w| {
+            let (a, b) = (&w[0], &w[1]);
+            (b.false_positive_rate() - a.false_positive_rate()) * (a.recall() + b.recall()) / 2.0
+        })
+        .sum()
+}
+
+/// What the chosen threshold optimizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// Highest recall with at least this precision
+    Precision(f64),
+    /// Highest precision with at least this recall
+    Recall(f64),
+    /// Best F1 score
+    F1,
+}
+
+/// Picks the threshold for `target`. The flag is false when no threshold reaches the target, in
+/// which case the best-F1 threshold is returned instead.
+pub fn choose_threshold(points: &[RocPoint], target: Target) -> Option<(RocPoint, bool)> {
+    let best_by = |eligible: &dyn Fn(&RocPoint) -> bool, score: &dyn Fn(&RocPoint) -> f64| {
+        points.iter().filter(|p| eligible(p)).copied()
+            // Prefer the lower threshold on ties, the conservative choice for deleting images
+            .fold(None, |best: Option<RocPoint>, p| match best {
+                Some(b) if score(&b) >= score(&p) => Some(b),
+                _ => Some(p),
+            })
+    };
+    let f1 = || best_by(&|p| p.tp > 0, &|p| p.f1());
+    match target {
+        Target::Precision(min) => best_by(&|p| p.tp > 0 && p.precision() >= min, &|p| p.recall()).map(|p| (p, true)),
+        Target::Recall(min) => best_by(&|p| p.recall() >= min, &|p| p.precision()).map(|p| (p, true)),
+        Target::F1 => f1().map(|p| (p, true)),
+    }
+    .or_else(|| f1().map(|p| (p, false)))
+}
+
+/// Renders a config with the calibrated thresholds. With a base config (the one calibration ran
+/// under), every other setting is kept, since the thresholds only hold under the same preprocessing.
+pub fn calibrated_config(base: Option<&str>, thresholds: &[(String, f64)], comment: &str) -> Result<String, String> {
+    let mut doc: toml::Table = match base {
+        Some(text) => text.parse().map_err(|e| format!("Failed to parse base config: {}", e))?,
+        None => toml::Table::new(),
+    };
+    let detectors = doc.entry("detectors").or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let detectors = detectors.as_table_mut().ok_or("`detectors` in the base config is not a table")?;
+    for (key, value) in thresholds {
+        detectors.insert(key.clone(), toml::Value::Float(*value));
+    }
+    let body = toml::to_string(&doc).map_err(|e| format!("Failed to render config: {}", e))?;
+    Ok(format!("{}\n{}", comment.lines().map(|l| format!("# {}\n", l)).collect::<String>(), body))
+}
+
+/// `grepfuzz calibrate`: scores the labeled set with every pixel detector, prints a summary per
+/// detector on stderr, and writes the calibrated config to `--output` (or stdout).
+pub fn run(args: &CalibrateArgs, config: &GrepfuzzConfig, config_path: Option<&str>) -> Result<(), String> {
+    let dataset = load_dataset(&args.dataset)?;
+    let target = match (args.target_precision, args.target_recall) {
+        (Some(p), None) => Target::Precision(p),
+        (None, Some(r)) => Target::Recall(r),
+        (None, None) => Target::F1,
+        (Some(_), Some(_)) => return Err("Give at most one of --target-precision and --target-recall".to_string()),
+    };
+    let detectors = build_detectors(0.0, 0.0, 0.0);
+    let cache = config.cache.path.as_deref().and_then(|p| crate::cache::ResultCache::open(Path::new(p)).map_err(|e| eprintln!("{}", e)).ok());
+
+    let mut samples: Vec<Vec<(f64, bool)>> = vec![Vec::new(); detectors.len()];
+    let mut failed = 0;
+    for image in &dataset {
+        match crate::process_image_cached(&image.path, &detectors, config, cache.as_ref()) {
+            Ok(report) => {
+                for (s, r) in samples.iter_mut().zip(&report.results) {
+                    s.push((r.value, image.blurry));
+                }
+            }
+            Err(e) => {
+                eprintln!("Error processing {}: {}", image.path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    let positives = samples[0].iter().filter(|(_, b)| *b).count();
+    if positives == 0 || positives == samples[0].len() {
+        return Err("Calibration needs both blurry and sharp images that decode".to_string());
+    }
+
+    let mut roc_out = match args.roc {
+        Some(ref path) => {
+            let mut f = fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            writeln!(f, "detector\tthreshold\ttrue_positive_rate\tfalse_positive_rate\tprecision").map_err(|e| e.to_string())?;
+            Some(f)
+        }
+        None => None,
+    };
+    let mut thresholds = Vec::new();
+    let mut summary = format!("Calibrated on {} images ({} blurry, {} sharp, {} unreadable)",
+        samples[0].len(), positives, samples[0].len() - positives, failed);
+    for (det, s) in detectors.iter().zip(&samples) {
+        let points = roc_curve(s);
+        if let Some(ref mut f) = roc_out {
+            for p in &points {
+                writeln!(f, "{}\t{}\t{:.4}\t{:.4}\t{:.4}", det.name(), p.threshold, p.recall(), p.false_positive_rate(), p.precision())
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        let Some((point, met)) = choose_threshold(&points, target) else { continue };
+        let line = format!("{}: AUC {:.3}, threshold {} (precision {:.3}, recall {:.3}, F1 {:.3}){}",
+            det.name(), auc(&points), point.threshold, point.precision(), point.recall(), point.f1(),
+            if met { "" } else { "; target not reachable, using best F1" });
+        eprintln!("{}", line);
+        summary.push('\n');
+        summary.push_str(&line);
+        thresholds.push((format!("{}_threshold", detector_key(det.name())), point.threshold));
+    }
+
+    let base = match config_path {
+        Some(p) => Some(fs::read_to_string(p).map_err(|e| format!("Failed to read config file: {}", e))?),
+        None => None,
+    };
+    let text = calibrated_config(base.as_deref(), &thresholds, &format!("Generated by grepfuzz calibrate\n{}", summary))?;
+    match args.output {
+        Some(ref path) => fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => io::stdout().write_all(text.as_bytes()).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roc_and_threshold_choice() {
+        // Blurry images score low, with one overlap
+        let samples = [(0.1, true), (0.2, true), (0.3, true), (0.35, false), (0.4, true), (0.6, false), (0.8, false)];
+        let points = roc_curve(&samples);
+        assert_eq!(points.len(), samples.len() + 1);
+        assert_eq!((points[0].tp, points[0].fp), (0, 0));
+        assert_eq!((points[7].tp, points[7].fp), (4, 3));
+        assert!((auc(&points) - 11.0 / 12.0).abs() < 1e-9);
+
+        let (p, met) = choose_threshold(&points, Target::Precision(1.0)).unwrap();
+        assert!(met && (p.threshold - 0.325).abs() < 1e-9 && p.recall() == 0.75);
+        let (p, _) = choose_threshold(&points, Target::Recall(1.0)).unwrap();
+        assert!((p.threshold - 0.5).abs() < 1e-9);
+        let (_, met) = choose_threshold(&points, Target::Precision(1.5)).unwrap();
+        assert!(!met);
+    }
+
+    #[test]
+    fn manifest_and_config_output() {
+        let dir = std::env::temp_dir().join(format!("grepfuzz-calibrate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("labels.csv");
+        fs::write(&manifest, "path,label\n# comment\na.jpg,blurry\nsub/b, c.jpg,sharp\n/abs/d.jpg\t1\n").unwrap();
+        let images = load_manifest(&manifest).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(images, vec![
+            LabeledImage { path: dir.join("a.jpg"), blurry: true },
+            LabeledImage { path: dir.join("sub/b, c.jpg"), blurry: false },
+            LabeledImage { path: PathBuf::from("/abs/d.jpg"), blurry: true },
+        ]);
+
+        let base = "[detectors]\nlaplacian_threshold = 0.2\n\n[exposure]\nnormalize = \"clahe\"\n";
+        let text = calibrated_config(Some(base), &[("laplacian_threshold".to_string(), 0.15)], "calibrated").unwrap();
+        assert!(text.starts_with("# calibrated\n"));
+        let config: GrepfuzzConfig = toml::from_str(&text).unwrap();
+        assert_eq!(config.detectors.laplacian_threshold, Some(0.15));
+        assert_eq!(config.exposure.normalize, Some(crate::exposure::Normalization::Clahe));
+    }
+}
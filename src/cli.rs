@@ -51,8 +51,332 @@ pub struct Cli {
     pub opencv_laplacian_threshold: Option<f64>,
 
     /// Config file path
-    #[arg(long = "config")]
+    #[arg(long = "config", global = true)]
     pub config: Option<String>,
+
+    /// Tiled analysis: split each image into an N x N grid and call it sharp if enough tiles are sharp
+    #[arg(long = "tiles")]
+    pub tiles: Option<u32>,
+
+    /// Number of sharpest tiles averaged into the reported tile score
+    #[arg(long = "tile-top-k")]
+    pub tile_top_k: Option<usize>,
+
+    /// Minimum fraction of sharp tiles (0.0-1.0) for a tiled image to count as sharp
+    #[arg(long = "min-sharp-fraction")]
+    pub min_sharp_fraction: Option<f64>,
+
+    /// Write a sharpness heatmap PNG per image (plus a NUL-separated manifest) into this directory
+    #[arg(long = "heatmap-out")]
+    pub heatmap_out: Option<String>,
+
+    /// Region of interest x,y,w,h in pixels or fractions (e.g. 0.2,0.2,0.6,0.6); repeatable. Any soft
+    /// region rejects the image, as does, with --tiles, a frame without enough sharp tiles
+    #[arg(long = "roi", value_parser = crate::roi::parse_roi)]
+    pub roi: Vec<crate::roi::Roi>,
+
+    /// Weight sharpness toward the frame center with a Gaussian falloff
+    #[arg(long = "center-weight", default_value_t = false)]
+    pub center_weight: bool,
+
+    /// Sigma of the center weighting, as a fraction of the frame size (default 0.35)
+    #[arg(long = "center-sigma")]
+    pub center_sigma: Option<f64>,
+
+    /// Estimate and report sensor noise (wavelet MAD) for each image
+    #[arg(long = "noise", default_value_t = false)]
+    pub noise: bool,
+
+    /// Subtract each detector's noise floor from its score so noise is not mistaken for detail
+    #[arg(long = "noise-compensate", default_value_t = false)]
+    pub noise_compensate: bool,
+
+    /// Normalize exposure/contrast before detection
+    #[arg(long = "normalize", value_enum)]
+    pub normalize: Option<crate::exposure::Normalization>,
+
+    /// Gamma for --normalize gamma (automatic when omitted)
+    #[arg(long = "gamma")]
+    pub gamma: Option<f64>,
+
+    /// Clip limit for --normalize clahe (default 2.0)
+    #[arg(long = "clahe-clip-limit")]
+    pub clahe_clip_limit: Option<f64>,
+
+    /// Report exposure metrics (mean luminance, clipped highlights, crushed shadows)
+    #[arg(long = "exposure", default_value_t = false)]
+    pub exposure: bool,
+
+    /// Include EXIF metadata (exposure, aperture, ISO, focal length, lens, camera, time, GPS) in --ascii output
+    #[arg(long = "metadata", default_value_t = false)]
+    pub metadata: bool,
+
+    /// Add the EXIF-based handheld shake-risk pseudo-detector (reciprocal rule)
+    #[arg(long = "shake-risk", default_value_t = false)]
+    pub shake_risk: bool,
+
+    /// Shake risk above which an image counts as blurry (1.0 = exactly the reciprocal rule)
+    #[arg(long = "shake-threshold")]
+    pub shake_threshold: Option<f64>,
+
+    /// Stops of stabilization that relax the reciprocal rule
+    #[arg(long = "stabilization-stops")]
+    pub stabilization_stops: Option<f64>,
+
+    /// Analyze every frame of animated GIF/WebP/APNG and every page of multipage TIFF
+    #[arg(long = "frames", default_value_t = false)]
+    pub frames: bool,
+
+    /// How frame verdicts decide the file verdict with --frames (default all)
+    #[arg(long = "frame-policy", value_enum)]
+    pub frame_policy: Option<crate::frames::FramePolicy>,
+
+    /// Analyze every Nth frame of video files
+    #[arg(long = "video-every-frames")]
+    pub video_every_frames: Option<u64>,
+
+    /// Analyze one video frame every N seconds (default 1.0)
+    #[arg(long = "video-every-seconds")]
+    pub video_every_seconds: Option<f64>,
+
+    /// Number of sharpest video frames to export (default 3)
+    #[arg(long = "video-top-k")]
+    pub video_top_k: Option<usize>,
+
+    /// Export the sharpest video frames as PNG into this directory
+    #[arg(long = "video-frames-out")]
+    pub video_frames_out: Option<String>,
+
+    /// Group piped images into bursts by EXIF capture time and camera; emit the rejects (or, with -s, the keepers)
+    #[arg(long = "bursts", default_value_t = false)]
+    pub bursts: bool,
+
+    /// Largest capture-time gap in seconds within a burst (default 1.0)
+    #[arg(long = "burst-gap")]
+    pub burst_gap: Option<f64>,
+
+    /// Number of sharpest images kept per burst (default 1)
+    #[arg(long = "burst-keep")]
+    pub burst_keep: Option<usize>,
+
+    /// Cluster piped images into near-duplicates by perceptual hash; emit the non-keepers (or, with -s, the sharpest of each)
+    #[arg(long = "dedup", default_value_t = false)]
+    pub dedup: bool,
+
+    /// Perceptual hash for --dedup (default dhash)
+    #[arg(long = "hash", value_enum)]
+    pub hash: Option<crate::dedup::HashKind>,
+
+    /// Largest Hamming distance (of 64 bits) between near-duplicates (default 10)
+    #[arg(long = "max-hash-distance")]
+    pub max_hash_distance: Option<u32>,
+
+    /// Relative mode: flag the softest N percent of each group of piped images instead of using absolute thresholds
+    #[arg(long = "bottom-percent")]
+    pub bottom_percent: Option<f64>,
+
+    /// Relative mode: flag piped images more than K standard deviations below their group's median
+    #[arg(long = "below-median-std")]
+    pub below_median_std: Option<f64>,
+
+    /// Images compared with each other in relative mode (default directory)
+    #[arg(long = "relative-group", value_enum)]
+    pub relative_group: Option<crate::relative::RelativeGroup>,
+
+    /// After a stdin batch, print counts, per-detector statistics and score histograms to stderr
+    #[arg(long = "summary", default_value_t = false)]
+    pub summary: bool,
+
+    /// Write the batch summary to this file instead of stderr
+    #[arg(long = "summary-out")]
+    pub summary_out: Option<String>,
+
+    /// Histogram bins per detector in the batch summary (default 10)
+    #[arg(long = "histogram-bins")]
+    pub histogram_bins: Option<usize>,
+
+    /// Write a self-contained HTML contact sheet of every analyzed image, sortable by detector score
+    #[arg(long = "report")]
+    pub report: Option<String>,
+
+    /// Longest thumbnail side in the HTML report, in pixels (default 256)
+    #[arg(long = "report-thumbnail-size")]
+    pub report_thumbnail_size: Option<u32>,
+
+    /// Write the verdict of every analyzed image to its XMP sidecar (rating, color label and scores), keeping existing content
+    #[arg(long = "xmp", default_value_t = false)]
+    pub xmp: bool,
+
+    /// Name of new XMP sidecars (default append: IMG.CR2.xmp)
+    #[arg(long = "xmp-naming", value_enum)]
+    pub xmp_naming: Option<crate::xmp::SidecarNaming>,
+
+    /// Move matching images (and their XMP sidecars) here, keeping their directory structure
+    #[arg(long = "move-to")]
+    pub move_to: Option<String>,
+
+    /// Copy matching images here, keeping their directory structure
+    #[arg(long = "copy-to")]
+    pub copy_to: Option<String>,
+
+    /// Link matching images here, keeping their directory structure
+    #[arg(long = "link-to")]
+    pub link_to: Option<String>,
+
+    /// Link flavor for --link-to (default hard)
+    #[arg(long = "link-kind", value_enum)]
+    pub link_kind: Option<crate::actions::LinkKind>,
+
+    /// Move matching images to the desktop trash, where the file manager can restore them
+    #[arg(long = "trash", default_value_t = false)]
+    pub trash: bool,
+
+    /// Apply the same action to images that do not match, into this directory
+    #[arg(long = "others-to")]
+    pub others_to: Option<String>,
+
+    /// Directory whose structure is mirrored below the destination (default: the working directory)
+    #[arg(long = "relative-to")]
+    pub relative_to: Option<String>,
+
+    /// Print the file actions that would be taken without touching any file
+    #[arg(long = "dry-run", default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Record every file action with its scores in this journal, for `grepfuzz undo` (default grepfuzz-journal.jsonl)
+    #[arg(long = "journal")]
+    pub journal: Option<String>,
+
+    /// Cache raw detector values in this SQLite file; unchanged images are not decoded again
+    #[arg(long = "cache")]
+    pub cache: Option<String>,
+
+    /// Also key the cache by file content, so touched or copied files stay cached
+    #[arg(long = "cache-content-hash", default_value_t = false)]
+    pub cache_content_hash: bool,
+
+    /// Decide the verdict with a model trained by `grepfuzz train` instead of detector thresholds
+    #[arg(long = "combiner")]
+    pub combiner: Option<String>,
+
+    /// Only emit images matching an expression, e.g. 'iso >= 3200 && laplacian.value < 50 && camera =~ "X-T4"'
+    #[arg(long = "where", value_parser = crate::filter_expr::parse_filter)]
+    pub where_expr: Option<crate::filter_expr::FilterExpr>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Compute per-detector ROC curves on a labeled set and write a config with calibrated thresholds
+    Calibrate(CalibrateArgs),
+    /// Score the configured detectors and verdict on a labeled set and print the metrics as JSON
+    Eval(EvalArgs),
+    /// Train a logistic verdict combiner on a labeled set and write it as TOML
+    Train(TrainArgs),
+    /// Reverse the file actions recorded in a journal, newest first, skipping files modified since
+    Undo(UndoArgs),
+    /// Analyze images as they land in a directory and handle each like a path read from stdin
+    Watch(WatchArgs),
+}
+
+/// The labeled set shared by `calibrate`, `eval` and `train`.
+#[derive(clap::Args, Debug)]
+pub struct DatasetArgs {
+    /// Manifest of `path,label` lines (label: blurry or sharp)
+    #[arg(long = "labels", conflicts_with_all = ["sharp_dir", "blurry_dir"])]
+    pub labels: Option<String>,
+
+    /// Directory of images known to be sharp
+    #[arg(long = "sharp-dir", requires = "blurry_dir")]
+    pub sharp_dir: Option<String>,
+
+    /// Directory of images known to be blurry
+    #[arg(long = "blurry-dir", requires = "sharp_dir")]
+    pub blurry_dir: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CalibrateArgs {
+    #[command(flatten)]
+    pub dataset: DatasetArgs,
+
+    /// Pick the threshold with the best recall that still flags blurry images with at least this precision
+    #[arg(long = "target-precision", conflicts_with = "target_recall")]
+    pub target_precision: Option<f64>,
+
+    /// Pick the threshold with the best precision that still catches at least this fraction of blurry images
+    #[arg(long = "target-recall")]
+    pub target_recall: Option<f64>,
+
+    /// Write the calibrated TOML config here instead of stdout
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+
+    /// Write every ROC point per detector to this TSV file
+    #[arg(long = "roc")]
+    pub roc: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct EvalArgs {
+    #[command(flatten)]
+    pub dataset: DatasetArgs,
+
+    /// Write the JSON report here instead of stdout
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TrainArgs {
+    #[command(flatten)]
+    pub dataset: DatasetArgs,
+
+    /// Write the model here instead of stdout
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+
+    /// L2 regularization strength (default 0.001)
+    #[arg(long = "l2")]
+    pub l2: Option<f64>,
+
+    /// Gradient descent iterations (default 2000)
+    #[arg(long = "iterations")]
+    pub iterations: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UndoArgs {
+    /// Journal written by --move-to, --copy-to, --link-to or --trash
+    pub journal: String,
+
+    /// Print what would be undone without touching any file
+    #[arg(long = "dry-run", default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Also undo actions whose destination was modified since
+    #[arg(long = "force", default_value_t = false)]
+    pub force: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct WatchArgs {
+    /// Directory to watch
+    pub dir: String,
+
+    /// Also watch subdirectories
+    #[arg(short = 'r', long = "recursive", default_value_t = false)]
+    pub recursive: bool,
+
+    /// Quiet period after a file's last write before it is analyzed, in milliseconds (default 500)
+    #[arg(long = "debounce")]
+    pub debounce: Option<u64>,
+
+    /// Skip the images already in the directory at startup
+    #[arg(long = "no-backlog", default_value_t = false)]
+    pub no_backlog: bool,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::blur_result::ImageReport;
+use crate::calibrate::load_dataset;
+use crate::cli::TrainArgs;
+use crate::config::GrepfuzzConfig;
+use crate::detector_helpers::detector_key;
+
+/// One standardized input of a [`LogisticModel`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feature {
+    /// Detector key (e.g. "laplacian") or metadata feature ("iso", "exposure_time", ...)
+    pub name: String,
+    pub mean: f64,
+    pub scale: f64,
+    pub weight: f64,
+}
+
+/// Logistic regression over detector values and capture metadata, trained by `grepfuzz train` and
+/// referenced from the config as the verdict combiner. Features missing from an image (no EXIF,
+/// a detector not run) count as their training mean, so they pull the verdict neither way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogisticModel {
+    /// Blur probability at or above which an image is called blurry
+    pub threshold: f64,
+    pub bias: f64,
+    pub features: Vec<Feature>,
+}
+
+/// Raw model inputs of a report. Detector values and the metadata that spans orders of magnitude
+/// are log-scaled, so a linear model sees roughly even steps.
+pub fn features_of(report: &ImageReport) -> Vec<(String, f64)> {
+    let mut features: Vec<(String, f64)> = report.results.iter()
+        .map(|r| (detector_key(&r.name), r.value.max(0.0).ln_1p()))
+        .collect();
+    let m = &report.metadata;
+    let metadata = [
+        ("iso", m.iso.map(|iso| (iso.max(1) as f64).log2())),
+        ("exposure_time", m.exposure_time.filter(|&t| t > 0.0).map(f64::log2)),
+        ("aperture", m.aperture),
+        ("focal_length", m.focal_length_35mm.or(m.focal_length)),
+    ];
+    features.extend(metadata.into_iter().filter_map(|(name, value)| Some((name.to_string(), value?))));
+    if report.width > 0 && report.height > 0 {
+        features.push(("megapixels".to_string(), report.width as f64 * report.height as f64 / 1e6));
+    }
+    features
+}
+
+impl LogisticModel {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read model {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("Failed to parse model {}: {}", path.display(), e))
+    }
+
+    /// Probability that an image with these raw features is blurry.
+    pub fn probability(&self, features: &[(String, f64)]) -> f64 {
+        let z = self.features.iter().fold(self.bias, |z, f| {
+            match features.iter().find(|(name, _)| *name == f.name) {
+                Some((_, value)) => z + f.weight * (value - f.mean) / f.scale,
+                None => z,
+            }
+        });
+        sigmoid(z)
+    }
+
+    /// Fits a model to `(features, blurry)` samples with full-batch gradient descent and L2
+    /// regularization. Features absent from every sample or constant across them are left out.
+    pub fn train(samples: &[(Vec<(String, f64)>, bool)], l2: f64, iterations: usize) -> Result<Self, String> {
+        let positives = samples.iter().filter(|(_, blurry)| *blurry).count();
+        if positives == 0 || positives == samples.len() {
+            return Err("Training needs both blurry and sharp images".to_string());
+        }
+        let mut names: Vec<&str> = Vec::new();
+        for (features, _) in samples {
+            for (name, _) in features {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        let mut features = Vec::new();
+        for name in names {
+            let values: Vec<f64> = samples.iter()
+                .filter_map(|(f, _)| f.iter().find(|(n, _)| n == name).map(|(_, v)| *v))
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let scale = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt();
+            if scale > 1e-12 {
+                features.push(Feature { name: name.to_string(), mean, scale, weight: 0.0 });
+            }
+        }
+        // Standardized design matrix; missing values sit at the mean (0)
+        let rows: Vec<Vec<f64>> = samples.iter()
+            .map(|(f, _)| features.iter().map(|feat| {
+                f.iter().find(|(n, _)| *n == feat.name).map_or(0.0, |(_, v)| (v - feat.mean) / feat.scale)
+            }).collect())
+            .collect();
+
+        let n = samples.len() as f64;
+        let rate = 0.5;
+        let mut bias = 0.0;
+        let mut weights = vec![0.0; features.len()];
+        for _ in 0..iterations {
+            let mut grad_bias = 0.0;
+            let mut grad = vec![0.0; weights.len()];
+            for (row, (_, blurry)) in rows.iter().zip(samples) {
+                let z = bias + row.iter().zip(&weights).map(|(x, w)| x * w).sum::<f64>();
+                let err = sigmoid(z) - if *blurry { 1.0 } else { 0.0 };
+                grad_bias += err;
+                for (g, x) in grad.iter_mut().zip(row) {
+                    *g += err * x;
+                }
+            }
+            bias -= rate * grad_bias / n;
+            for (w, g) in weights.iter_mut().zip(&grad) {
+                *w -= rate * (g / n + l2 * *w);
+            }
+        }
+        for (feature, weight) in features.iter_mut().zip(weights) {
+            feature.weight = weight;
+        }
+        Ok(Self { threshold: 0.5, bias, features })
+    }
+}
+
+fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+/// `grepfuzz train`: analyzes a labeled set with the configured detectors, fits a logistic model
+/// and writes it as TOML to `--output` (or stdout), with the training accuracy on stderr.
+pub fn run(args: &TrainArgs, config: &GrepfuzzConfig) -> Result<(), String> {
+    let dataset = load_dataset(&args.dataset)?;
+    let detectors = crate::detector_helpers::build_configured_detectors(config);
+    let cache = config.cache.path.as_deref().and_then(|p| crate::cache::ResultCache::open(Path::new(p)).map_err(|e| eprintln!("{}", e)).ok());
+
+    let mut samples = Vec::new();
+    for image in &dataset {
+        match crate::process_image_cached(&image.path, &detectors, config, cache.as_ref()) {
+            Ok(report) => samples.push((features_of(&report), image.blurry)),
+            Err(e) => eprintln!("Error processing {}: {}", image.path.display(), e),
+        }
+    }
+    let model = LogisticModel::train(&samples, args.l2.unwrap_or(0.001), args.iterations.unwrap_or(2000))?;
+    let correct = samples.iter().filter(|(f, blurry)| (model.probability(f) >= model.threshold) == *blurry).count();
+    eprintln!("Trained on {} images: training accuracy {:.3}", samples.len(), correct as f64 / samples.len() as f64);
+    for f in &model.features {
+        eprintln!("  {}: weight {:.4}", f.name, f.weight);
+    }
+
+    let body = toml::to_string(&model).map_err(|e| format!("Failed to render model: {}", e))?;
+    let text = format!("# Generated by grepfuzz train on {} images\n{}", samples.len(), body);
+    match args.output {
+        Some(ref path) => fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(laplacian: f64, iso: Option<f64>, blurry: bool) -> (Vec<(String, f64)>, bool) {
+        let mut f = vec![("laplacian".to_string(), laplacian)];
+        f.extend(iso.map(|v| ("iso".to_string(), v)));
+        (f, blurry)
+    }
+
+    #[test]
+    fn learns_a_separable_set() {
+        let samples = [
+            sample(0.1, Some(10.0), true), sample(0.2, None, true), sample(0.3, Some(8.0), true),
+            sample(0.8, Some(7.0), false), sample(0.9, Some(9.0), false), sample(1.0, None, false),
+        ];
+        let model = LogisticModel::train(&samples, 0.001, 2000).unwrap();
+        assert_eq!(model.features.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["laplacian", "iso"]);
+        assert!(model.features[0].weight < 0.0);
+        for (features, blurry) in &samples {
+            assert_eq!(model.probability(features) >= model.threshold, *blurry);
+        }
+        // Survives a TOML round trip unchanged
+        let text = toml::to_string(&model).unwrap();
+        assert_eq!(toml::from_str::<LogisticModel>(&text).unwrap(), model);
+        assert!(LogisticModel::train(&samples[..3], 0.001, 10).is_err());
+    }
+
+    #[test]
+    fn report_features_are_log_scaled() {
+        let mut report = ImageReport { width: 2000, height: 1000, ..ImageReport::default() };
+        report.metadata.iso = Some(800);
+        report.results.push(crate::blur_result::BlurResult { name: "Tenengrad".to_string(), value: std::f64::consts::E - 1.0, threshold: 100.0, is_blurry: true, higher_is_blurrier: false });
+        let features = features_of(&report);
+        assert_eq!(features[0].0, "tenengrad");
+        assert!((features[0].1 - 1.0).abs() < 1e-12);
+        assert!(features.contains(&("iso".to_string(), 800f64.log2())));
+        assert!(features.contains(&("megapixels".to_string(), 2.0)));
+    }
+}
@@ -1,6 +1,17 @@
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 use serde::Deserialize;
+use crate::actions::LinkKind;
+use crate::combiner::LogisticModel;
+use crate::dedup::HashKind;
+use crate::exposure::Normalization;
+use crate::filter_expr::FilterExpr;
+use crate::frames::FramePolicy;
+use crate::relative::RelativeGroup;
+use crate::roi::Roi;
+use crate::video::Sampling;
+use crate::xmp::SidecarNaming;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DetectorConfig {
@@ -10,9 +21,341 @@ pub struct DetectorConfig {
     // Add more detector thresholds as needed
 }
 
+/// Tiled (per-region) analysis settings. Tiling is enabled when `grid` is set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TileConfig {
+    /// Number of tiles per side (grid x grid tiles)
+    pub grid: Option<u32>,
+    /// Number of sharpest tiles averaged into the reported top-k score
+    pub top_k: Option<usize>,
+    /// Minimum fraction of sharp tiles for the image to count as sharp
+    pub min_sharp_fraction: Option<f64>,
+}
+
+impl TileConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.grid.is_some()
+    }
+}
+
+/// Heatmap export settings. Export is enabled when `dir` is set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HeatmapConfig {
+    /// Output directory for heatmap PNGs and their manifest
+    pub dir: Option<String>,
+    /// Tiles per side when tiled analysis is not enabled
+    pub grid: Option<u32>,
+    /// Longest side of the downscaled heatmap, in pixels
+    pub max_size: Option<u32>,
+}
+
+/// Region-of-interest and center-weighting settings, applied before any detector runs.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RoiConfig {
+    /// Regions as "x,y,w,h" in pixels or fractions of the frame
+    pub regions: Option<Vec<Roi>>,
+    /// Attenuate detail away from the frame center with a Gaussian falloff
+    pub center_weight: Option<bool>,
+    /// Gaussian sigma as a fraction of the frame size
+    pub center_sigma: Option<f64>,
+}
+
+/// Noise estimation settings. Estimation is enabled by `estimate` or `compensate`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct NoiseConfig {
+    /// Estimate and report the noise level of each image
+    pub estimate: Option<bool>,
+    /// Subtract each detector's noise floor from its score before thresholding
+    pub compensate: Option<bool>,
+    /// Blend the measured noise with an estimate derived from EXIF ISO (default true)
+    pub iso_prior: Option<bool>,
+    /// Expected noise sigma at ISO 100, in 8-bit gray levels
+    pub iso_base_sigma: Option<f64>,
+}
+
+impl NoiseConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.estimate.unwrap_or(false) || self.compensate.unwrap_or(false)
+    }
+}
+
+/// Exposure normalization and metrics settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExposureConfig {
+    /// Normalization applied before detection: none, stretch, clahe or gamma
+    pub normalize: Option<Normalization>,
+    /// Fixed gamma for `gamma` normalization (automatic when unset)
+    pub gamma: Option<f64>,
+    /// CLAHE clip limit, as a multiple of the uniform histogram bin height
+    pub clahe_clip_limit: Option<f64>,
+    /// Report clipped highlights, crushed shadows and mean luminance
+    pub metrics: Option<bool>,
+}
+
+/// Metadata reporting settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MetadataConfig {
+    /// Append EXIF fields as key=value columns to `--ascii` output
+    pub report: Option<bool>,
+}
+
+/// Handheld shake-risk pseudo-detector settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ShakeConfig {
+    /// Predict shake from EXIF exposure time and focal length
+    pub enabled: Option<bool>,
+    /// Flag images whose exposure exceeds the reciprocal rule by more than this factor (default 1.0)
+    pub threshold: Option<f64>,
+    /// Stops of image stabilization (lens or body) the rule is relaxed by
+    pub stabilization_stops: Option<f64>,
+    /// Crop factor used when EXIF lacks a 35mm-equivalent focal length (default 1.0)
+    pub crop_factor: Option<f64>,
+    /// Let the shake prediction take part in the blurry verdict (default true)
+    pub include_in_verdict: Option<bool>,
+}
+
+/// Multi-frame (animated GIF/WebP/APNG, multipage TIFF) analysis settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct FrameConfig {
+    /// Analyze every frame or page instead of only the first
+    pub enabled: Option<bool>,
+    /// How frame verdicts combine into the file verdict: any, all or mean (default all; any for video)
+    pub policy: Option<FramePolicy>,
+}
+
+impl FrameConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+}
+
+/// Video sampling and sharpest-frame export settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct VideoConfig {
+    /// Analyze every Nth frame
+    pub every_frames: Option<u64>,
+    /// Analyze one frame every N seconds (default 1.0 when `every_frames` is unset)
+    pub every_seconds: Option<f64>,
+    /// Number of sharpest frames to export (default 3)
+    pub top_k: Option<usize>,
+    /// Directory the sharpest frames are exported to as PNG
+    pub export_dir: Option<String>,
+}
+
+impl VideoConfig {
+    pub fn sampling(&self) -> Sampling {
+        match self.every_frames {
+            Some(n) => Sampling::EveryFrames(n.max(1)),
+            None => Sampling::EverySeconds(self.every_seconds.unwrap_or(1.0)),
+        }
+    }
+}
+
+/// Burst grouping settings (stdin mode only, since bursts span many files).
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BurstConfig {
+    /// Group images into bursts and select the sharpest of each
+    pub enabled: Option<bool>,
+    /// Largest capture-time gap, in seconds, between consecutive shots of one burst (default 1.0)
+    pub gap_seconds: Option<f64>,
+    /// Number of images kept per burst (default 1)
+    pub keep: Option<usize>,
+}
+
+impl BurstConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+}
+
+/// Near-duplicate detection settings (stdin mode only, since clusters span many files).
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DedupConfig {
+    /// Cluster near-duplicates by perceptual hash and keep the sharpest of each
+    pub enabled: Option<bool>,
+    /// Perceptual hash: dhash or phash (default dhash)
+    pub hash: Option<HashKind>,
+    /// Largest Hamming distance, out of 64 bits, between near-duplicates (default 10)
+    pub max_distance: Option<u32>,
+}
+
+impl DedupConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+}
+
+/// Batch-relative thresholding (stdin mode only): flags the worst images of each group instead of
+/// judging each image against absolute thresholds. Enabled when either criterion is set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RelativeConfig {
+    /// Flag this percentage of each group, softest first
+    pub bottom_percent: Option<f64>,
+    /// Flag images more than this many standard deviations below their group's median
+    pub below_median_std: Option<f64>,
+    /// Images compared with each other: all, directory or burst (default directory)
+    pub group: Option<RelativeGroup>,
+}
+
+impl RelativeConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.bottom_percent.is_some() || self.below_median_std.is_some()
+    }
+}
+
+/// End-of-batch summary settings (stdin mode only). Enabled by `enabled` or `path`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SummaryConfig {
+    /// Print the summary to stderr
+    pub enabled: Option<bool>,
+    /// Write the summary to this file instead of stderr
+    pub path: Option<String>,
+    /// Histogram bins per detector (default 10)
+    pub bins: Option<usize>,
+}
+
+impl SummaryConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false) || self.path.is_some()
+    }
+}
+
+/// HTML contact-sheet settings. The report is written when `path` is set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ReportConfig {
+    /// Self-contained HTML file with a thumbnail per analyzed image
+    pub path: Option<String>,
+    /// Longest thumbnail side in pixels (default 256)
+    pub thumbnail_size: Option<u32>,
+}
+
+/// XMP sidecar writing settings. When enabled, every analyzed image's sidecar gets a rating, a
+/// color label and the grepfuzz scores. Where no sharp rating or label is set, a kept image loses the
+/// one an earlier run gave it and any other is left as it is.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct XmpConfig {
+    pub enabled: Option<bool>,
+    /// Name of new sidecars: append (IMG.CR2.xmp, default) or replace (IMG.xmp)
+    pub naming: Option<SidecarNaming>,
+    /// xmp:Rating for rejected images (default 1; -1 marks them rejected in Lightroom and darktable)
+    pub blurry_rating: Option<i32>,
+    /// xmp:Label for rejected images (default "Red")
+    pub blurry_label: Option<String>,
+    /// xmp:Rating for kept images (default: remove the one grepfuzz wrote)
+    pub sharp_rating: Option<i32>,
+    /// xmp:Label for kept images (default: remove the one grepfuzz wrote)
+    pub sharp_label: Option<String>,
+}
+
+impl XmpConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+}
+
+/// What to do with the files of matching images. At most one of `move_to`, `copy_to`, `link_to`
+/// and `trash` may be set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ActionConfig {
+    pub move_to: Option<String>,
+    pub copy_to: Option<String>,
+    pub link_to: Option<String>,
+    /// hard (default) or symbolic links for `link_to`
+    pub link_kind: Option<LinkKind>,
+    /// Move matches to the desktop trash
+    pub trash: Option<bool>,
+    /// Apply the same action to the images that did not match, into this directory
+    pub others_to: Option<String>,
+    /// Directory whose structure is mirrored below the destination (default: the working directory)
+    pub base: Option<String>,
+    /// Print what would be done without touching any file
+    pub dry_run: Option<bool>,
+    /// Append-only JSON-lines journal of every operation, for `grepfuzz undo` (default grepfuzz-journal.jsonl)
+    pub journal: Option<String>,
+}
+
+/// Persistent result cache settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CacheConfig {
+    /// SQLite database holding raw detector values between runs
+    pub path: Option<String>,
+    /// Also key entries by a hash of the file content, so touched or copied files stay cached
+    pub content_hash: Option<bool>,
+}
+
+/// Trained verdict combiner. When `model` is set, its blur probability replaces the
+/// threshold-based verdict.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CombinerConfig {
+    /// TOML model written by `grepfuzz train`
+    pub model: Option<String>,
+    /// The model loaded from `model`
+    #[serde(skip)]
+    pub loaded: Option<Arc<LogisticModel>>,
+}
+
+impl CombinerConfig {
+    fn load(model: Option<String>) -> Self {
+        let loaded = model.as_deref().and_then(|path| match LogisticModel::load(Path::new(path)) {
+            Ok(m) => Some(Arc::new(m)),
+            Err(e) => {
+                eprintln!("{}. Using detector thresholds.", e);
+                None
+            }
+        });
+        Self { model, loaded }
+    }
+}
+
+/// Post-detection filtering settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct FilterConfig {
+    /// Only emit images whose analysis record matches this expression
+    #[serde(rename = "where")]
+    pub where_expr: Option<FilterExpr>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct GrepfuzzConfig {
     pub detectors: DetectorConfig,
+    #[serde(default)]
+    pub tiles: TileConfig,
+    #[serde(default)]
+    pub heatmap: HeatmapConfig,
+    #[serde(default)]
+    pub roi: RoiConfig,
+    #[serde(default)]
+    pub noise: NoiseConfig,
+    #[serde(default)]
+    pub exposure: ExposureConfig,
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+    #[serde(default)]
+    pub shake: ShakeConfig,
+    #[serde(default)]
+    pub frames: FrameConfig,
+    #[serde(default)]
+    pub video: VideoConfig,
+    #[serde(default)]
+    pub bursts: BurstConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    #[serde(default)]
+    pub relative: RelativeConfig,
+    #[serde(default)]
+    pub summary: SummaryConfig,
+    #[serde(default)]
+    pub report: ReportConfig,
+    #[serde(default)]
+    pub xmp: XmpConfig,
+    #[serde(default)]
+    pub actions: ActionConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub combiner: CombinerConfig,
+    #[serde(default)]
+    pub filter: FilterConfig,
 }
 
 impl Default for GrepfuzzConfig {
@@ -23,11 +366,35 @@ impl Default for GrepfuzzConfig {
                 tenengrad_threshold: Some(100.0),
                 opencv_laplacian_threshold: Some(55.0),
             },
+            tiles: TileConfig::default(),
+            heatmap: HeatmapConfig::default(),
+            roi: RoiConfig::default(),
+            noise: NoiseConfig::default(),
+            exposure: ExposureConfig::default(),
+            metadata: MetadataConfig::default(),
+            shake: ShakeConfig::default(),
+            frames: FrameConfig::default(),
+            video: VideoConfig::default(),
+            bursts: BurstConfig::default(),
+            dedup: DedupConfig::default(),
+            relative: RelativeConfig::default(),
+            summary: SummaryConfig::default(),
+            report: ReportConfig::default(),
+            xmp: XmpConfig::default(),
+            actions: ActionConfig::default(),
+            cache: CacheConfig::default(),
+            combiner: CombinerConfig::default(),
+            filter: FilterConfig::default(),
         }
     }
 }
 
 impl GrepfuzzConfig {
+    /// True if the image passes the `where` filter (or no filter is configured).
+    pub fn filter_matches(&self, report: &crate::blur_result::ImageReport, path: &str) -> bool {
+        self.filter.where_expr.as_ref().is_none_or(|f| f.matches(report, path))
+    }
+
     /// Loads config from file if provided, otherwise uses default. Merges CLI overrides.
     pub fn from_cli(cli: &crate::cli::Cli) -> Self {
         let config = if let Some(ref path) = cli.config {
@@ -49,6 +416,98 @@ impl GrepfuzzConfig {
                 opencv_laplacian_threshold: cli.opencv_laplacian_threshold.or(config.detectors.opencv_laplacian_threshold),
                 // Add more fields as needed
             },
+            tiles: TileConfig {
+                grid: cli.tiles.or(config.tiles.grid),
+                top_k: cli.tile_top_k.or(config.tiles.top_k),
+                min_sharp_fraction: cli.min_sharp_fraction.or(config.tiles.min_sharp_fraction),
+            },
+            heatmap: HeatmapConfig {
+                dir: cli.heatmap_out.clone().or(config.heatmap.dir),
+                ..config.heatmap
+            },
+            roi: RoiConfig {
+                regions: if cli.roi.is_empty() { config.roi.regions } else { Some(cli.roi.clone()) },
+                center_weight: if cli.center_weight { Some(true) } else { config.roi.center_weight },
+                center_sigma: cli.center_sigma.or(config.roi.center_sigma),
+            },
+            noise: NoiseConfig {
+                estimate: if cli.noise { Some(true) } else { config.noise.estimate },
+                compensate: if cli.noise_compensate { Some(true) } else { config.noise.compensate },
+                ..config.noise
+            },
+            exposure: ExposureConfig {
+                normalize: cli.normalize.or(config.exposure.normalize),
+                gamma: cli.gamma.or(config.exposure.gamma),
+                clahe_clip_limit: cli.clahe_clip_limit.or(config.exposure.clahe_clip_limit),
+                metrics: if cli.exposure { Some(true) } else { config.exposure.metrics },
+            },
+            metadata: MetadataConfig {
+                report: if cli.metadata { Some(true) } else { config.metadata.report },
+            },
+            shake: ShakeConfig {
+                enabled: if cli.shake_risk { Some(true) } else { config.shake.enabled },
+                threshold: cli.shake_threshold.or(config.shake.threshold),
+                stabilization_stops: cli.stabilization_stops.or(config.shake.stabilization_stops),
+                ..config.shake
+            },
+            frames: FrameConfig {
+                enabled: if cli.frames { Some(true) } else { config.frames.enabled },
+                policy: cli.frame_policy.or(config.frames.policy),
+            },
+            video: VideoConfig {
+                every_frames: cli.video_every_frames.or(config.video.every_frames),
+                every_seconds: cli.video_every_seconds.or(config.video.every_seconds),
+                top_k: cli.video_top_k.or(config.video.top_k),
+                export_dir: cli.video_frames_out.clone().or(config.video.export_dir),
+            },
+            bursts: BurstConfig {
+                enabled: if cli.bursts { Some(true) } else { config.bursts.enabled },
+                gap_seconds: cli.burst_gap.or(config.bursts.gap_seconds),
+                keep: cli.burst_keep.or(config.bursts.keep),
+            },
+            dedup: DedupConfig {
+                enabled: if cli.dedup { Some(true) } else { config.dedup.enabled },
+                hash: cli.hash.or(config.dedup.hash),
+                max_distance: cli.max_hash_distance.or(config.dedup.max_distance),
+            },
+            relative: RelativeConfig {
+                bottom_percent: cli.bottom_percent.or(config.relative.bottom_percent),
+                below_median_std: cli.below_median_std.or(config.relative.below_median_std),
+                group: cli.relative_group.or(config.relative.group),
+            },
+            summary: SummaryConfig {
+                enabled: if cli.summary { Some(true) } else { config.summary.enabled },
+                path: cli.summary_out.clone().or(config.summary.path),
+                bins: cli.histogram_bins.or(config.summary.bins),
+            },
+            report: ReportConfig {
+                path: cli.report.clone().or(config.report.path),
+                thumbnail_size: cli.report_thumbnail_size.or(config.report.thumbnail_size),
+            },
+            xmp: XmpConfig {
+                enabled: if cli.xmp { Some(true) } else { config.xmp.enabled },
+                naming: cli.xmp_naming.or(config.xmp.naming),
+                ..config.xmp
+            },
+            actions: ActionConfig {
+                move_to: cli.move_to.clone().or(config.actions.move_to),
+                copy_to: cli.copy_to.clone().or(config.actions.copy_to),
+                link_to: cli.link_to.clone().or(config.actions.link_to),
+                link_kind: cli.link_kind.or(config.actions.link_kind),
+                trash: if cli.trash { Some(true) } else { config.actions.trash },
+                others_to: cli.others_to.clone().or(config.actions.others_to),
+                base: cli.relative_to.clone().or(config.actions.base),
+                dry_run: if cli.dry_run { Some(true) } else { config.actions.dry_run },
+                journal: cli.journal.clone().or(config.actions.journal),
+            },
+            cache: CacheConfig {
+                path: cli.cache.clone().or(config.cache.path),
+                content_hash: if cli.cache_content_hash { Some(true) } else { config.cache.content_hash },
+            },
+            combiner: CombinerConfig::load(cli.combiner.clone().or(config.combiner.model)),
+            filter: FilterConfig {
+                where_expr: cli.where_expr.clone().or(config.filter.where_expr),
+            },
         }
     }
 
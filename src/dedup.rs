@@ -0,0 +1,176 @@
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Luma};
+use serde::Deserialize;
+use crate::blur_result::ImageReport;
+use crate::burst::combined_score;
+
+/// Perceptual hash used to find near-duplicates.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashKind {
+    /// Difference hash: brightness gradients of a 9x8 thumbnail (fast)
+    Dhash,
+    /// DCT hash: low frequencies of a 32x32 thumbnail (robust to re-edits)
+    Phash,
+}
+
+/// Where an image landed in near-duplicate clustering.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateMembership {
+    /// Zero-based cluster number, in order of each cluster's first appearance in the input.
+    pub cluster: usize,
+    /// Number of images in the cluster; 1 for an image without duplicates.
+    pub size: usize,
+    /// True for the sharpest member, the one to keep.
+    pub keeper: bool,
+    /// Hamming distance between this image's hash and the keeper's.
+    pub distance: u32,
+}
+
+pub fn perceptual_hash(img: &ImageBuffer<Luma<u8>, Vec<u8>>, kind: HashKind) -> u64 {
+    match kind {
+        HashKind::Dhash => dhash(img),
+        HashKind::Phash => phash(img),
+    }
+}
+
+/// 64-bit difference hash: bit set where a pixel of the 9x8 thumbnail is brighter than its right neighbor.
+pub fn dhash(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> u64 {
+    let small = imageops::resize(img, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | (small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0]) as u64;
+        }
+    }
+    hash
+}
+
+/// 64-bit DCT hash: bit set where a coefficient of the 8x8 lowest frequencies (of a 32x32
+/// thumbnail's DCT) exceeds their median. The DC term (overall brightness) is left out of the median.
+pub fn phash(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> u64 {
+    const N: usize = 32;
+    let small = imageops::resize(img, N as u32, N as u32, FilterType::Triangle);
+    let pixels: Vec<f64> = small.pixels().map(|p| p[0] as f64).collect();
+    let cos: Vec<f64> = (0..8 * N)
+        .map(|i| {
+            let (u, x) = (i / N, i % N);
+            (std::f64::consts::PI * (2 * x + 1) as f64 * u as f64 / (2 * N) as f64).cos()
+        })
+        .collect();
+    // Separable DCT-II, only the 8 lowest frequencies in each direction
+    let mut rows = vec![0.0; N * 8];
+    for y in 0..N {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..N).map(|x| pixels[y * N + x] * cos[u * N + x]).sum();
+        }
+    }
+    let mut coeffs = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coeffs[v * 8 + u] = (0..N).map(|y| rows[y * 8 + u] * cos[v * N + y]).sum();
+        }
+    }
+    let mut sorted: Vec<f64> = coeffs[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    coeffs.iter().fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Clusters images whose hashes are at most `max_distance` bits apart (transitively) and marks the
+/// sharpest member of each cluster, by the same combined score burst ranking uses, as the keeper.
+/// Images without a hash are clusters of their own. Returns one membership per report, in input order.
+pub fn cluster_duplicates(reports: &[&ImageReport], max_distance: u32) -> Vec<DuplicateMembership> {
+    let n = reports.len();
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..n {
+        let Some(a) = reports[i].hash else { continue };
+        for (j, other) in reports.iter().enumerate().skip(i + 1) {
+            if let Some(b) = other.hash {
+                if hamming(a, b) <= max_distance {
+                    let (ri, rj) = (find(&mut parent, i), find(&mut parent, j));
+                    // Keep the earliest image as the root so clusters number in input order
+                    parent[ri.max(rj)] = ri.min(rj);
+                }
+            }
+        }
+    }
+
+    let roots: Vec<usize> = (0..n).map(|i| find(&mut parent, i)).collect();
+    let scores: Vec<f64> = reports.iter().map(|r| combined_score(&r.results)).collect();
+    // Per root: cluster number, member count and sharpest member (earliest on ties)
+    let mut cluster_of = vec![usize::MAX; n];
+    let mut sizes = vec![0; n];
+    let mut keepers: Vec<usize> = (0..n).collect();
+    let mut clusters = 0;
+    for i in 0..n {
+        let root = roots[i];
+        if cluster_of[root] == usize::MAX {
+            cluster_of[root] = clusters;
+            clusters += 1;
+        }
+        sizes[root] += 1;
+        if scores[i] > scores[keepers[root]] {
+            keepers[root] = i;
+        }
+    }
+    (0..n)
+        .map(|i| {
+            let root = roots[i];
+            let keeper = keepers[root];
+            let distance = match (reports[i].hash, reports[keeper].hash) {
+                (Some(a), Some(b)) => hamming(a, b),
+                _ => 0,
+            };
+            DuplicateMembership { cluster: cluster_of[root], size: sizes[root], keeper: keeper == i, distance }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blur_result::BlurResult;
+
+    fn scene(shift: u32) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(64, 48, |x, y| Luma([((x * 3 + y * 2 + shift) % 200) as u8 + if x > 32 { 50 } else { 0 }]))
+    }
+
+    #[test]
+    fn hashes_survive_mild_edits() {
+        let img = scene(0);
+        let brighter = ImageBuffer::from_fn(64, 48, |x, y| Luma([img.get_pixel(x, y)[0].saturating_add(5)]));
+        let other: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(64, 48, |x, y| Luma([if (x / 8 + y / 8) % 2 == 0 { 20 } else { 230 }]));
+        for kind in [HashKind::Dhash, HashKind::Phash] {
+            let (a, b, c) = (perceptual_hash(&img, kind), perceptual_hash(&brighter, kind), perceptual_hash(&other, kind));
+            assert!(hamming(a, b) <= 4, "{:?}: {}", kind, hamming(a, b));
+            assert!(hamming(a, c) > 10, "{:?}: {}", kind, hamming(a, c));
+        }
+    }
+
+    #[test]
+    fn sharpest_member_is_keeper() {
+        let make = |hash: u64, value: f64| ImageReport {
+            results: vec![BlurResult { name: "LaplacianVariance".to_string(), value, threshold: 1.0, is_blurry: value < 1.0, higher_is_blurrier: false }],
+            hash: Some(hash),
+            ..ImageReport::default()
+        };
+        let reports = [make(0, 1.0), make(u64::MAX, 3.0), make(0b111, 2.0)];
+        let refs: Vec<&ImageReport> = reports.iter().collect();
+        let dups = cluster_duplicates(&refs, 5);
+        assert_eq!(dups.iter().map(|d| d.cluster).collect::<Vec<_>>(), vec![0, 1, 0]);
+        assert_eq!(dups.iter().map(|d| d.keeper).collect::<Vec<_>>(), vec![false, true, true]);
+        assert_eq!((dups[0].size, dups[0].distance), (2, 3));
+    }
+}
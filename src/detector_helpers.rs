@@ -2,6 +2,8 @@ use crate::blur_detector::BlurDetector;
 use crate::blur_laplacian::LaplacianVarianceDetector;
 use crate::blur_tenengrad::TenengradDetector;
 use crate::blur_opencv::OpenCvLaplacianDetector;
+use crate::blur_shake::ShakeRiskDetector;
+use crate::config::GrepfuzzConfig;
 
 pub fn build_detectors(laplacian_threshold: f64, tenengrad_threshold: f64, opencv_laplacian_threshold: f64) -> Vec<Box<dyn BlurDetector>> {
     vec![
@@ -10,3 +12,85 @@ pub fn build_detectors(laplacian_threshold: f64, tenengrad_threshold: f64, openc
         Box::new(OpenCvLaplacianDetector::new(opencv_laplacian_threshold)),
     ]
 }
+
+/// Laplacian, Tenengrad and OpenCV Laplacian thresholds of `config`. A config file may leave
+/// thresholds out; they fall back to the same defaults as without one.
+pub fn configured_thresholds(config: &GrepfuzzConfig) -> (f64, f64, f64) {
+    let defaults = GrepfuzzConfig::default().detectors;
+    (
+        config.detectors.laplacian_threshold.or(defaults.laplacian_threshold).unwrap_or(0.2),
+        config.detectors.tenengrad_threshold.or(defaults.tenengrad_threshold).unwrap_or(100.0),
+        config.detectors.opencv_laplacian_threshold.or(defaults.opencv_laplacian_threshold).unwrap_or(55.0),
+    )
+}
+
+pub fn build_configured_detectors(config: &GrepfuzzConfig) -> Vec<Box<dyn BlurDetector>> {
+    let (laplacian, tenengrad, opencv_laplacian) = configured_thresholds(config);
+    build_detectors(laplacian, tenengrad, opencv_laplacian)
+}
+
+/// Returns the threshold of a known detector, or 0.0 for unknown implementations.
+pub fn detector_threshold(det: &dyn BlurDetector) -> f64 {
+    if let Some(l) = det.as_any().downcast_ref::<LaplacianVarianceDetector>() {
+        l.threshold
+    } else if let Some(t) = det.as_any().downcast_ref::<TenengradDetector>() {
+        t.threshold
+    } else if let Some(o) = det.as_any().downcast_ref::<OpenCvLaplacianDetector>() {
+        o.threshold
+    } else if let Some(s) = det.as_any().downcast_ref::<ShakeRiskDetector>() {
+        s.threshold
+    } else { 0.0 }
+}
+
+/// ShakeRisk grows with blur; every pixel detector shrinks.
+pub fn higher_is_blurrier(det: &dyn BlurDetector) -> bool {
+    det.as_any().is::<ShakeRiskDetector>()
+}
+
+/// Classifies a raw detector value against the detector's threshold, the way its `detect` would.
+pub fn classify(det: &dyn BlurDetector, value: f64) -> bool {
+    let threshold = detector_threshold(det);
+    if higher_is_blurrier(det) {
+        value > threshold
+    } else {
+        value < threshold
+    }
+}
+
+/// Short snake_case key for a detector name, as used in `--where` expressions
+/// (e.g. "LaplacianVariance" -> "laplacian", "OpenCV Laplacian" -> "opencv_laplacian").
+pub fn detector_key(name: &str) -> String {
+    match name {
+        "LaplacianVariance" => "laplacian".to_string(),
+        // Splitting at case changes would give "open_cv_laplacian"
+        "OpenCV Laplacian" => "opencv_laplacian".to_string(),
+        _ => {
+            let mut key = String::new();
+            let mut prev_lower = false;
+            for c in name.chars() {
+                if c.is_ascii_uppercase() && prev_lower {
+                    key.push('_');
+                }
+                if c.is_alphanumeric() {
+                    key.push(c.to_ascii_lowercase());
+                } else if !key.ends_with('_') {
+                    key.push('_');
+                }
+                prev_lower = c.is_ascii_lowercase();
+            }
+            key
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_of_built_in_detectors() {
+        let keys: Vec<String> = build_detectors(0.2, 100.0, 55.0).iter().map(|d| detector_key(d.name())).collect();
+        assert_eq!(keys, ["laplacian", "tenengrad", "opencv_laplacian"]);
+        assert_eq!(detector_key("ShakeRisk"), "shake_risk");
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::calibrate::load_dataset;
+use crate::cli::EvalArgs;
+use crate::config::GrepfuzzConfig;
+
+/// Confusion matrix with "blurry" as the positive class.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Confusion {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Misclassified {
+    pub path: PathBuf,
+    /// "blurry" or "sharp"
+    pub label: &'static str,
+    pub predicted: &'static str,
+}
+
+/// Quality of one classifier (a single detector, or the combined verdict) on the labeled set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub name: String,
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub confusion: Confusion,
+    pub misclassified: Vec<Misclassified>,
+}
+
+/// Machine-readable result of `grepfuzz eval`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvalReport {
+    /// Labeled images that were analyzed.
+    pub images: usize,
+    /// Images that could not be analyzed and are left out of every metric.
+    pub unreadable: Vec<PathBuf>,
+    /// The verdict grepfuzz emits, after every configured pass and combiner.
+    pub verdict: Evaluation,
+    pub detectors: Vec<Evaluation>,
+}
+
+fn label(blurry: bool) -> &'static str {
+    if blurry { "blurry" } else { "sharp" }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 { 0.0 } else { a as f64 / b as f64 }
+}
+
+/// Scores `(path, actual, predicted)` triples, where `true` means blurry.
+pub fn evaluate(name: &str, outcomes: &[(&Path, bool, bool)]) -> Evaluation {
+    let mut c = Confusion::default();
+    let mut misclassified = Vec::new();
+    for &(path, actual, predicted) in outcomes {
+        match (actual, predicted) {
+            (true, true) => c.true_positives += 1,
+            (false, true) => c.false_positives += 1,
+            (false, false) => c.true_negatives += 1,
+            (true, false) => c.false_negatives += 1,
+        }
+        if actual != predicted {
+            misclassified.push(Misclassified { path: path.to_path_buf(), label: label(actual), predicted: label(predicted) });
+        }
+    }
+    Evaluation {
+        name: name.to_string(),
+        accuracy: ratio(c.true_positives + c.true_negatives, outcomes.len()),
+        precision: ratio(c.true_positives, c.true_positives + c.false_positives),
+        recall: ratio(c.true_positives, c.true_positives + c.false_negatives),
+        f1: ratio(2 * c.true_positives, 2 * c.true_positives + c.false_positives + c.false_negatives),
+        confusion: c,
+        misclassified,
+    }
+}
+
+/// `grepfuzz eval`: runs the configured detectors over the labeled set, writes an [`EvalReport`] as
+/// JSON to `--output` (or stdout) and a one-line summary per classifier to stderr.
+pub fn run(args: &EvalArgs, config: &GrepfuzzConfig) -> Result<(), String> {
+    let dataset = load_dataset(&args.dataset)?;
+    let detectors = crate::detector_helpers::build_configured_detectors(config);
+    let cache = config.cache.path.as_deref().and_then(|p| crate::cache::ResultCache::open(Path::new(p)).map_err(|e| eprintln!("{}", e)).ok());
+
+    let mut verdicts = Vec::new();
+    // Per result name, in order of first appearance (pseudo-detectors only show up with EXIF)
+    let mut per_detector: Vec<(String, Vec<(&Path, bool, bool)>)> = Vec::new();
+    let mut unreadable = Vec::new();
+    for image in &dataset {
+        let report = match crate::process_image_cached(&image.path, &detectors, config, cache.as_ref()) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error processing {}: {}", image.path.display(), e);
+                unreadable.push(image.path.clone());
+                continue;
+            }
+        };
+        verdicts.push((image.path.as_path(), image.blurry, report.is_blurry));
+        for r in &report.results {
+            let outcome = (image.path.as_path(), image.blurry, r.is_blurry);
+            match per_detector.iter_mut().find(|(name, _)| *name == r.name) {
+                Some((_, outcomes)) => outcomes.push(outcome),
+                None => per_detector.push((r.name.clone(), vec![outcome])),
+            }
+        }
+    }
+
+    let report = EvalReport {
+        images: verdicts.len(),
+        unreadable,
+        verdict: evaluate("verdict", &verdicts),
+        detectors: per_detector.iter().map(|(name, outcomes)| evaluate(name, outcomes)).collect(),
+    };
+    for e in std::iter::once(&report.verdict).chain(&report.detectors) {
+        let c = &e.confusion;
+        eprintln!("{}: accuracy {:.3}, precision {:.3}, recall {:.3}, F1 {:.3} (TP {}, FP {}, TN {}, FN {})",
+            e.name, e.accuracy, e.precision, e.recall, e.f1, c.true_positives, c.false_positives, c.true_negatives, c.false_negatives);
+    }
+    let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to render report: {}", e))? + "\n";
+    match args.output {
+        Some(ref path) => fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => io::stdout().write_all(json.as_bytes()).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_and_misclassified() {
+        let (a, b, c, d, e) = (Path::new("a"), Path::new("b"), Path::new("c"), Path::new("d"), Path::new("e"));
+        let eval = evaluate("verdict", &[(a, true, true), (b, true, false), (c, false, false), (d, false, true), (e, true, true)]);
+        assert_eq!(eval.confusion, Confusion { true_positives: 2, false_positives: 1, true_negatives: 1, false_negatives: 1 });
+        assert_eq!((eval.accuracy, eval.precision, eval.recall), (0.6, 2.0 / 3.0, 2.0 / 3.0));
+        assert_eq!(eval.misclassified, vec![
+            Misclassified { path: PathBuf::from("b"), label: "blurry", predicted: "sharp" },
+            Misclassified { path: PathBuf::from("d"), label: "sharp", predicted: "blurry" },
+        ]);
+        let json = serde_json::to_value(&eval).unwrap();
+        assert_eq!(json["confusion"]["false_negatives"], 1);
+        assert_eq!(json["misclassified"][0]["path"], "b");
+    }
+}
@@ -0,0 +1,219 @@
+use image::{ImageBuffer, Luma};
+use serde::Deserialize;
+
+/// Pixel values at or above this count as clipped highlights.
+const HIGHLIGHT_CLIP: u8 = 250;
+/// Pixel values at or below this count as crushed shadows.
+const SHADOW_CRUSH: u8 = 5;
+
+/// Contrast/exposure normalization applied before any detector runs.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Leave pixels untouched
+    None,
+    /// Linear stretch of the 1st-99th percentile range to full scale
+    Stretch,
+    /// Contrast-limited adaptive histogram equalization
+    Clahe,
+    /// Gamma correction (automatic unless a gamma is given)
+    Gamma,
+}
+
+/// Exposure statistics of the image as captured (before normalization).
+#[derive(Debug, Clone)]
+pub struct ExposureMetrics {
+    /// Mean luminance, 0-255.
+    pub mean_luminance: f64,
+    /// Fraction of pixels at or near white.
+    pub clipped_highlights: f64,
+    /// Fraction of pixels at or near black.
+    pub crushed_shadows: f64,
+}
+
+fn histogram(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> [u64; 256] {
+    let mut hist = [0u64; 256];
+    for p in img.pixels() {
+        hist[p[0] as usize] += 1;
+    }
+    hist
+}
+
+pub fn exposure_metrics(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> ExposureMetrics {
+    let hist = histogram(img);
+    let n = hist.iter().sum::<u64>().max(1) as f64;
+    let mean_luminance = hist.iter().enumerate().map(|(v, &c)| v as f64 * c as f64).sum::<f64>() / n;
+    let clipped = hist[HIGHLIGHT_CLIP as usize..].iter().sum::<u64>() as f64 / n;
+    let crushed = hist[..=SHADOW_CRUSH as usize].iter().sum::<u64>() as f64 / n;
+    ExposureMetrics { mean_luminance, clipped_highlights: clipped, crushed_shadows: crushed }
+}
+
+/// Applies `mode` to `img`. `gamma` overrides the automatic gamma; `clip_limit` tunes CLAHE.
+pub fn normalize(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    mode: Normalization,
+    gamma: Option<f64>,
+    clip_limit: Option<f64>,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    match mode {
+        Normalization::None => img.clone(),
+        Normalization::Stretch => stretch(img, 0.01, 0.99),
+        Normalization::Clahe => clahe(img, 8, clip_limit.unwrap_or(2.0)),
+        Normalization::Gamma => {
+            let g = gamma.unwrap_or_else(|| auto_gamma(img));
+            apply_gamma(img, g)
+        }
+    }
+}
+
+/// Maps the `low`..`high` percentile range of the histogram onto 0..255.
+pub fn stretch(img: &ImageBuffer<Luma<u8>, Vec<u8>>, low: f64, high: f64) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let hist = histogram(img);
+    let n = hist.iter().sum::<u64>() as f64;
+    let percentile = |q: f64| {
+        let target = q * n;
+        let mut acc = 0.0;
+        for (v, &c) in hist.iter().enumerate() {
+            acc += c as f64;
+            if acc >= target {
+                return v as f64;
+            }
+        }
+        255.0
+    };
+    let (lo, hi) = (percentile(low), percentile(high));
+    if hi <= lo {
+        return img.clone();
+    }
+    let lut: Vec<u8> = (0..256)
+        .map(|v| (((v as f64 - lo) / (hi - lo)) * 255.0).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    apply_lut(img, &lut)
+}
+
+/// Gamma that maps the current mean luminance to mid-gray.
+pub fn auto_gamma(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> f64 {
+    let mean = exposure_metrics(img).mean_luminance / 255.0;
+    if mean <= 0.0 || mean >= 1.0 {
+        return 1.0;
+    }
+    (0.5f64.ln() / mean.ln()).clamp(0.1, 10.0)
+}
+
+pub fn apply_gamma(img: &ImageBuffer<Luma<u8>, Vec<u8>>, gamma: f64) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let lut: Vec<u8> = (0..256)
+        .map(|v| ((v as f64 / 255.0).powf(gamma) * 255.0).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    apply_lut(img, &lut)
+}
+
+fn apply_lut(img: &ImageBuffer<Luma<u8>, Vec<u8>>, lut: &[u8]) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| Luma([lut[img.get_pixel(x, y)[0] as usize]]))
+}
+
+/// Contrast-limited adaptive histogram equalization over a `grid`x`grid` layout of tiles.
+///
+/// Each tile's histogram is clipped at `clip_limit` times the uniform bin height (the excess is
+/// redistributed evenly) and turned into an equalization curve; pixels are mapped by bilinear
+/// interpolation between the curves of the four nearest tile centers to avoid block seams.
+pub fn clahe(img: &ImageBuffer<Luma<u8>, Vec<u8>>, grid: u32, clip_limit: f64) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let (width, height) = (img.width(), img.height());
+    let grid = grid.max(1).min(width.max(1)).min(height.max(1));
+    let tile_w = width.div_ceil(grid).max(1);
+    let tile_h = height.div_ceil(grid).max(1);
+
+    let mut luts = vec![[0u8; 256]; (grid * grid) as usize];
+    for ty in 0..grid {
+        for tx in 0..grid {
+            let mut hist = [0f64; 256];
+            let (x0, y0) = (tx * tile_w, ty * tile_h);
+            let (x1, y1) = ((x0 + tile_w).min(width), (y0 + tile_h).min(height));
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    hist[img.get_pixel(x, y)[0] as usize] += 1.0;
+                }
+            }
+            let count = ((x1 - x0) * (y1 - y0)).max(1) as f64;
+            let limit = (clip_limit * count / 256.0).max(1.0);
+            let mut excess = 0.0;
+            for h in hist.iter_mut() {
+                if *h > limit {
+                    excess += *h - limit;
+                    *h = limit;
+                }
+            }
+            let bonus = excess / 256.0;
+            let lut = &mut luts[(ty * grid + tx) as usize];
+            let mut cdf = 0.0;
+            for (v, h) in hist.iter().enumerate() {
+                cdf += h + bonus;
+                lut[v] = (cdf / count * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let v = img.get_pixel(x, y)[0] as usize;
+        // Position relative to tile centers
+        let fx = ((x as f64 + 0.5) / tile_w as f64 - 0.5).clamp(0.0, (grid - 1) as f64);
+        let fy = ((y as f64 + 0.5) / tile_h as f64 - 0.5).clamp(0.0, (grid - 1) as f64);
+        let (tx0, ty0) = (fx.floor() as u32, fy.floor() as u32);
+        let (tx1, ty1) = ((tx0 + 1).min(grid - 1), (ty0 + 1).min(grid - 1));
+        let (ax, ay) = (fx - tx0 as f64, fy - ty0 as f64);
+        let at = |tx: u32, ty: u32| luts[(ty * grid + tx) as usize][v] as f64;
+        let top = at(tx0, ty0) * (1.0 - ax) + at(tx1, ty0) * ax;
+        let bottom = at(tx0, ty1) * (1.0 - ax) + at(tx1, ty1) * ax;
+        Luma([(top * (1.0 - ay) + bottom * ay).round() as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(values: &[u8]) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(values.len() as u32, 1, |x, _| Luma([values[x as usize]]))
+    }
+
+    fn flat(value: u8) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_pixel(16, 16, Luma([value]))
+    }
+
+    #[test]
+    fn metrics_from_a_known_histogram() {
+        let m = exposure_metrics(&row(&[0, 100, 200, 255]));
+        assert_eq!(m.mean_luminance, 138.75);
+        assert_eq!((m.clipped_highlights, m.crushed_shadows), (0.25, 0.25));
+    }
+
+    #[test]
+    fn stretch_maps_percentiles_to_full_scale() {
+        let ramp: Vec<u8> = (50..150).collect();
+        let out = stretch(&row(&ramp), 0.01, 0.99);
+        assert_eq!((out.get_pixel(0, 0)[0], out.get_pixel(98, 0)[0], out.get_pixel(99, 0)[0]), (0, 255, 255));
+        assert!(out.pixels().zip(out.pixels().skip(1)).all(|(a, b)| a[0] <= b[0]));
+        // A flat image has no range to stretch
+        assert_eq!(stretch(&flat(77), 0.01, 0.99), flat(77));
+    }
+
+    #[test]
+    fn auto_gamma_targets_mid_gray() {
+        let dark = flat(64);
+        let corrected = apply_gamma(&dark, auto_gamma(&dark));
+        assert!((corrected.get_pixel(0, 0)[0] as i32 - 128).abs() <= 1);
+        // Black and white images have no usable mean
+        assert_eq!((auto_gamma(&flat(0)), auto_gamma(&flat(255))), (1.0, 1.0));
+    }
+
+    #[test]
+    fn clahe_handles_flat_and_tiny_images() {
+        let out = clahe(&flat(128), 8, 2.0);
+        let first = out.get_pixel(0, 0)[0];
+        assert!(out.pixels().all(|p| p[0] == first));
+        assert_eq!(clahe(&row(&[9]), 8, 2.0).dimensions(), (1, 1));
+        // Equalization spreads a narrow ramp out
+        let ramp: Vec<u8> = (100..132).collect();
+        let eq = clahe(&row(&ramp), 1, 40.0);
+        assert!(eq.get_pixel(31, 0)[0] - eq.get_pixel(0, 0)[0] > 31);
+    }
+}
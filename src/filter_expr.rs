@@ -0,0 +1,420 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::blur_result::ImageReport;
+use crate::detector_helpers::detector_key;
+
+/// A `--where` filter expression over the analysis record of one image.
+///
+/// Grammar (lowest to highest precedence):
+/// ```text
+/// expr   := and ("||" and)*
+/// and    := unary ("&&" unary)*
+/// unary  := "!" unary | cmp
+/// cmp    := atom (("==" | "!=" | "<" | "<=" | ">" | ">=" | "=~" | "!~") atom)?
+/// atom   := ["-"] NUMBER | NUMBER "/" NUMBER | "string" | true | false | FIELD | "(" expr ")"
+/// ```
+/// Fields are metadata keys (`iso`, `aperture`, `exposure_time`, `camera`, `lens`, ...), image
+/// properties (`path`, `size`, `width`, `height`, `blurry`, `noise`, `mean_luminance`, ...) and
+/// per-detector `<detector>.value`, `<detector>.threshold` and `<detector>.blurry`, where the
+/// detector is `laplacian`, `tenengrad`, `opencv_laplacian` or `shake_risk`. A comparison
+/// against a field the image does not have is false.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FilterExpr {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Or(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Cmp(Box<Node>, CmpOp, Box<Node>),
+    Match(Box<Node>, Regex, bool),
+    Literal(Value),
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A value in the analysis record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Num(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Bool(b) => *b,
+        }
+    }
+
+    fn as_text(&self) -> String {
+        match self {
+            Value::Num(n) => n.to_string(),
+            Value::Str(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    const OPS: [&str; 13] = ["||", "&&", "==", "!=", "<=", ">=", "=~", "!~", "<", ">", "!", "/", "-"];
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("Unterminated string in '{}'", src)),
+                    Some(&q) if q == c => break,
+                    Some('\\') if chars.get(i + 1).is_some() => {
+                        s.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&ch) => {
+                        s.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push(Token::Str(s));
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text.parse().map_err(|_| format!("Invalid number '{}'", text))?;
+            tokens.push(Token::Num(n));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match OPS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                }
+                None => return Err(format!("Unexpected '{}' in '{}'", c, src)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut left = self.and()?;
+        while self.eat_op("||") {
+            left = Node::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut left = self.unary()?;
+        while self.eat_op("&&") {
+            left = Node::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat_op("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Node, String> {
+        let left = self.atom()?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => return Ok(left),
+        };
+        let cmp = match op {
+            "==" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            ">=" => CmpOp::Ge,
+            "=~" | "!~" => {
+                self.pos += 1;
+                let pattern = match self.atom()? {
+                    Node::Literal(v) => v.as_text(),
+                    _ => return Err(format!("Right side of {} must be a literal pattern", op)),
+                };
+                let re = Regex::new(&pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+                return Ok(Node::Match(Box::new(left), re, op == "=~"));
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Node::Cmp(Box::new(left), cmp, Box::new(self.atom()?)))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let token = self.peek().cloned().ok_or("Unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Op("-") => match self.atom()? {
+                Node::Literal(Value::Num(n)) => Ok(Node::Literal(Value::Num(-n))),
+                _ => Err("Expected a number after '-'".to_string()),
+            },
+            Token::Num(n) => {
+                // Allow shutter-speed style fractions such as 1/60
+                if self.eat_op("/") {
+                    match self.peek() {
+                        Some(Token::Num(d)) if *d != 0.0 => {
+                            let d = *d;
+                            self.pos += 1;
+                            Ok(Node::Literal(Value::Num(n / d)))
+                        }
+                        _ => Err("Expected a non-zero number after '/'".to_string()),
+                    }
+                } else {
+                    Ok(Node::Literal(Value::Num(n)))
+                }
+            }
+            Token::Str(s) => Ok(Node::Literal(Value::Str(s))),
+            Token::Ident(name) => Ok(match name.as_str() {
+                "true" => Node::Literal(Value::Bool(true)),
+                "false" => Node::Literal(Value::Bool(false)),
+                _ => Node::Field(name),
+            }),
+            Token::LParen => {
+                let inner = self.expr()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err("Expected ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            other => Err(format!("Unexpected {:?}", other)),
+        }
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0 };
+        let root = parser.expr()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("Unexpected {:?} in '{}'", parser.tokens[parser.pos], s));
+        }
+        Ok(FilterExpr { source: s.to_string(), root })
+    }
+}
+
+impl TryFrom<String> for FilterExpr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// clap value parser for `--where`.
+pub fn parse_filter(s: &str) -> Result<FilterExpr, String> {
+    s.parse()
+}
+
+impl FilterExpr {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression with `lookup` resolving field names.
+    pub fn eval_with<F: Fn(&str) -> Option<Value>>(&self, lookup: &F) -> bool {
+        eval(&self.root, lookup).is_some_and(|v| v.truthy())
+    }
+
+    /// Evaluates the expression against the analysis record of `path`.
+    pub fn matches(&self, report: &ImageReport, path: &str) -> bool {
+        self.eval_with(&|name| record_field(report, path, name))
+    }
+}
+
+fn eval<F: Fn(&str) -> Option<Value>>(node: &Node, lookup: &F) -> Option<Value> {
+    let truthy = |n: &Node| eval(n, lookup).is_some_and(|v| v.truthy());
+    Some(match node {
+        Node::Or(a, b) => Value::Bool(truthy(a) || truthy(b)),
+        Node::And(a, b) => Value::Bool(truthy(a) && truthy(b)),
+        Node::Not(a) => Value::Bool(!truthy(a)),
+        Node::Literal(v) => v.clone(),
+        Node::Field(name) => lookup(name)?,
+        Node::Match(a, re, want) => match eval(a, lookup) {
+            Some(v) => Value::Bool(re.is_match(&v.as_text()) == *want),
+            None => Value::Bool(false),
+        },
+        Node::Cmp(a, op, b) => {
+            let ordering = match (eval(a, lookup), eval(b, lookup)) {
+                (Some(Value::Num(x)), Some(Value::Num(y))) => x.partial_cmp(&y),
+                (Some(Value::Str(x)), Some(Value::Str(y))) => Some(x.cmp(&y)),
+                (Some(Value::Bool(x)), Some(Value::Bool(y))) => Some(x.cmp(&y)),
+                _ => None,
+            };
+            Value::Bool(match ordering {
+                Some(o) => match op {
+                    CmpOp::Eq => o.is_eq(),
+                    CmpOp::Ne => o.is_ne(),
+                    CmpOp::Lt => o.is_lt(),
+                    CmpOp::Le => o.is_le(),
+                    CmpOp::Gt => o.is_gt(),
+                    CmpOp::Ge => o.is_ge(),
+                },
+                // Missing fields and mismatched types never satisfy a comparison
+                None => false,
+            })
+        }
+    })
+}
+
+/// Resolves one field of the analysis record.
+pub fn record_field(report: &ImageReport, path: &str, name: &str) -> Option<Value> {
+    if let Some((det, attr)) = name.split_once('.') {
+        let res = report.results.iter().find(|r| detector_key(&r.name) == det)?;
+        return match attr {
+            "value" => Some(Value::Num(res.value)),
+            "threshold" => Some(Value::Num(res.threshold)),
+            "blurry" => Some(Value::Bool(res.is_blurry)),
+            _ => None,
+        };
+    }
+    match name {
+        "path" => return Some(Value::Str(path.to_string())),
+        "size" => return Some(Value::Num(report.size as f64)),
+        "width" => return Some(Value::Num(report.width as f64)),
+        "height" => return Some(Value::Num(report.height as f64)),
+        "blurry" => return Some(Value::Bool(report.is_blurry)),
+        "sharp" => return Some(Value::Bool(!report.is_blurry)),
+        "burst" => return report.burst.as_ref().map(|b| Value::Num(b.id as f64)),
+        "burst_rank" => return report.burst.as_ref().map(|b| Value::Num(b.rank as f64)),
+        "burst_keep" => return report.burst.as_ref().map(|b| Value::Bool(b.keep)),
+        "dup_cluster" => return report.duplicate.as_ref().map(|d| Value::Num(d.cluster as f64)),
+        "dup_size" => return report.duplicate.as_ref().map(|d| Value::Num(d.size as f64)),
+        "dup_keeper" => return report.duplicate.as_ref().map(|d| Value::Bool(d.keeper)),
+        "rel_rank" => return report.relative.as_ref().map(|r| Value::Num(r.rank as f64)),
+        "rel_deviation" => return report.relative.as_ref().map(|r| Value::Num(r.deviation)),
+        "rel_flagged" => return report.relative.as_ref().map(|r| Value::Bool(r.flagged)),
+        "p_blurry" => return report.blur_probability.map(Value::Num),
+        "frames" => return Some(Value::Num(report.frames.len().max(1) as f64)),
+        "noise" => return report.noise.as_ref().map(|n| Value::Num(n.sigma)),
+        "mean_luminance" => return report.exposure.as_ref().map(|e| Value::Num(e.mean_luminance)),
+        "clipped_highlights" => return report.exposure.as_ref().map(|e| Value::Num(e.clipped_highlights)),
+        "crushed_shadows" => return report.exposure.as_ref().map(|e| Value::Num(e.crushed_shadows)),
+        _ => {}
+    }
+    let (_, text) = report.metadata.fields().into_iter().find(|(key, _)| *key == name)?;
+    Some(match text.parse::<f64>() {
+        Ok(n) => Value::Num(n),
+        Err(_) => Value::Str(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<Value> {
+        match name {
+            "iso" => Some(Value::Num(6400.0)),
+            "exposure_time" => Some(Value::Num(1.0 / 15.0)),
+            "laplacian.value" => Some(Value::Num(12.5)),
+            "camera" => Some(Value::Str("X-T4".to_string())),
+            "blurry" => Some(Value::Bool(true)),
+            _ => None,
+        }
+    }
+
+    fn eval_str(src: &str) -> bool {
+        src.parse::<FilterExpr>().unwrap().eval_with(&lookup)
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        assert!(eval_str(r#"iso >= 3200 && laplacian.value < 50 && camera =~ "X-T4""#));
+        assert!(eval_str("exposure_time > 1/30 || iso < 100"));
+        assert!(eval_str("!(iso < 100) && blurry"));
+        assert!(!eval_str(r#"camera !~ "^X-""#));
+        assert!(eval_str("iso > -1"));
+    }
+
+    #[test]
+    fn test_missing_field_is_false() {
+        assert!(!eval_str("aperture < 2.8"));
+        assert!(eval_str("!(aperture < 2.8)"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("iso >=".parse::<FilterExpr>().is_err());
+        assert!("(iso > 1".parse::<FilterExpr>().is_err());
+        assert!("camera =~ \"[\"".parse::<FilterExpr>().is_err());
+        assert!("iso > 1 2".parse::<FilterExpr>().is_err());
+    }
+}
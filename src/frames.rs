@@ -0,0 +1,240 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::metadata::Orientation;
+use image::{AnimationDecoder, DynamicImage, ImageBuffer, ImageFormat, ImageReader, Luma};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult, Limits};
+use tiff::ColorType;
+use crate::blur_result::BlurResult;
+
+/// How per-frame verdicts combine into the verdict for the whole file.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FramePolicy {
+    /// The file is sharp if any frame is sharp
+    Any,
+    /// The file is sharp only if every frame is sharp (one blurry page flags the file)
+    All,
+    /// Each detector's value is averaged over the frames and thresholded once (with tiles or
+    /// regions, the frames' verdicts are averaged instead)
+    Mean,
+}
+
+/// Detector results for one frame or page.
+pub struct FrameReport {
+    /// Zero-based frame or page index.
+    pub index: usize,
+    /// Presentation time in seconds, for video frames.
+    pub timestamp: Option<f64>,
+    pub results: Vec<BlurResult>,
+    pub is_blurry: bool,
+    /// Where the frame was exported as PNG, if it was.
+    pub export: Option<PathBuf>,
+}
+
+/// Combines per-frame verdicts under `policy`.
+///
+/// `Mean` averages each detector's value over the frames, flags a detector when that mean is on the
+/// blurry side of its threshold (above it for detectors where higher means blurrier), and, like a single frame, calls the file blurry only when every detector agrees.
+/// When tiles or regions decide each frame's verdict (`regional`), no single value per detector
+/// stands for the frame, so `Mean` averages the verdicts instead: the file is blurry when at least
+/// half of its frames are.
+pub fn aggregate(frames: &[FrameReport], policy: FramePolicy, regional: bool) -> bool {
+    match policy {
+        FramePolicy::Any => frames.iter().all(|f| f.is_blurry),
+        FramePolicy::All => frames.iter().any(|f| f.is_blurry),
+        FramePolicy::Mean if regional => 2 * frames.iter().filter(|f| f.is_blurry).count() >= frames.len(),
+        FramePolicy::Mean => {
+            let detectors = frames.first().map_or(0, |f| f.results.len());
+            (0..detectors).all(|i| {
+                let mean = frames.iter().map(|f| f.results[i].value).sum::<f64>() / frames.len() as f64;
+                let first = &frames[0].results[i];
+                if first.higher_is_blurrier { mean > first.threshold } else { mean < first.threshold }
+            })
+        }
+    }
+}
+
+/// Rotates and flips a frame by an EXIF orientation (1-8), the way the first frame was decoded.
+pub fn orient(frame: ImageBuffer<Luma<u8>, Vec<u8>>, orientation: u8) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    match Orientation::from_exif(orientation) {
+        Some(o) if o != Orientation::NoTransforms => {
+            let mut img = DynamicImage::ImageLuma8(frame);
+            img.apply_orientation(o);
+            img.into_luma8()
+        }
+        _ => frame,
+    }
+}
+
+/// Decodes the frames of an animated GIF, WebP or APNG, or the pages of a TIFF, as grayscale,
+/// calling `visit` with each frame's index in order. Animation frames are composited onto the full
+/// canvas. Returns the number of frames; other formats, and still PNG/WebP, return 0 unvisited.
+pub fn visit_frames<F>(path: &Path, mut visit: F) -> Result<usize, String>
+where
+    F: FnMut(usize, ImageBuffer<Luma<u8>, Vec<u8>>),
+{
+    let err = |e: &dyn std::fmt::Display| format!("Failed to decode frames of {}: {}", path.display(), e);
+    let format = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(|e| err(&e))?
+        .format();
+    let open = || File::open(path).map(BufReader::new).map_err(|e| err(&e));
+    let frames = match format {
+        Some(ImageFormat::Gif) => GifDecoder::new(open()?).map_err(|e| err(&e))?.into_frames(),
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(open()?).map_err(|e| err(&e))?;
+            if !decoder.has_animation() {
+                return Ok(0);
+            }
+            decoder.into_frames()
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(open()?).map_err(|e| err(&e))?;
+            if !decoder.is_apng().map_err(|e| err(&e))? {
+                return Ok(0);
+            }
+            decoder.apng().map_err(|e| err(&e))?.into_frames()
+        }
+        Some(ImageFormat::Tiff) => return visit_tiff_pages(path, visit),
+        _ => return Ok(0),
+    };
+    let mut count = 0;
+    for frame in frames {
+        let frame = frame.map_err(|e| err(&e))?;
+        visit(count, DynamicImage::ImageRgba8(frame.into_buffer()).to_luma8());
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// TIFF pages are read with the `tiff` crate directly, since `image` only decodes the first one.
+fn visit_tiff_pages<F>(path: &Path, mut visit: F) -> Result<usize, String>
+where
+    F: FnMut(usize, ImageBuffer<Luma<u8>, Vec<u8>>),
+{
+    let err = |page: usize, e: &dyn std::fmt::Display| format!("Failed to decode page {} of {}: {}", page, path.display(), e);
+    let file = File::open(path).map(BufReader::new).map_err(|e| err(0, &e))?;
+    // Scanned pages easily exceed the default buffer limit
+    let mut decoder = TiffDecoder::new(file).map_err(|e| err(0, &e))?.with_limits(Limits::unlimited());
+    let mut count = 0;
+    loop {
+        let (width, height) = decoder.dimensions().map_err(|e| err(count, &e))?;
+        let color = decoder.colortype().map_err(|e| err(count, &e))?;
+        let data = decoder.read_image().map_err(|e| err(count, &e))?;
+        let page = tiff_page_to_luma(width, height, color, data)
+            .ok_or_else(|| err(count, &format!("unsupported color type {:?}", color)))?;
+        visit(count, page);
+        count += 1;
+        if !decoder.more_images() {
+            return Ok(count);
+        }
+        decoder.next_image().map_err(|e| err(count, &e))?;
+    }
+}
+
+fn tiff_page_to_luma(width: u32, height: u32, color: ColorType, data: DecodingResult) -> Option<ImageBuffer<Luma<u8>, Vec<u8>>> {
+    let img = match (color, data) {
+        (ColorType::Gray(8), DecodingResult::U8(d)) => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, d)?),
+        // Bilevel and low-depth scans: rows are packed MSB-first and padded to whole bytes
+        (ColorType::Gray(bits @ (1 | 2 | 4)), DecodingResult::U8(d)) => {
+            let bits = bits as u32;
+            let row_bytes = (width * bits).div_ceil(8) as usize;
+            let max = (1u32 << bits) - 1;
+            if d.len() < row_bytes * height as usize {
+                return None;
+            }
+            return Some(ImageBuffer::from_fn(width, height, |x, y| {
+                let bit = x * bits;
+                let byte = d[y as usize * row_bytes + (bit / 8) as usize] as u32;
+                let v = (byte >> (8 - bits - bit % 8)) & max;
+                Luma([(v * 255 / max) as u8])
+            }));
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(d)) => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::RGB(8), DecodingResult::U8(d)) => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::RGBA(8), DecodingResult::U8(d)) => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::CMYK(8), DecodingResult::U8(d)) => {
+            let rgb = d.chunks_exact(4)
+                .flat_map(|p| {
+                    let k = 255 - p[3] as u32;
+                    [0, 1, 2].map(|c| ((255 - p[c] as u32) * k / 255) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, rgb)?)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(d)) => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::GrayA(16), DecodingResult::U16(d)) => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::RGB(16), DecodingResult::U16(d)) => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, d)?),
+        (ColorType::RGBA(16), DecodingResult::U16(d)) => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, d)?),
+        _ => return None,
+    };
+    Some(img.to_luma8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(index: usize, values: &[f64], is_blurry: bool) -> FrameReport {
+        let results = values.iter()
+            .map(|&value| BlurResult { name: "d".to_string(), value, threshold: 1.0, is_blurry: value < 1.0, higher_is_blurrier: false })
+            .collect();
+        FrameReport { index, timestamp: None, results, is_blurry, export: None }
+    }
+
+    #[test]
+    fn policies_combine_frames() {
+        let frames = [frame(0, &[2.0], false), frame(1, &[0.5], true), frame(2, &[0.2], true)];
+        assert!(!aggregate(&frames, FramePolicy::Any, false));
+        assert!(aggregate(&frames, FramePolicy::All, false));
+        // Mean of 0.9 is below the threshold of 1.0
+        assert!(aggregate(&frames, FramePolicy::Mean, false));
+        assert!(!aggregate(&frames[..2], FramePolicy::Mean, false));
+        // With tiles or regions, half the frames being blurry is enough
+        assert!(aggregate(&frames[..2], FramePolicy::Mean, true));
+        assert!(!aggregate(&[frame(0, &[0.1], false), frame(1, &[0.1], false), frame(2, &[2.0], true)], FramePolicy::Mean, true));
+    }
+
+    #[test]
+    fn mean_respects_rising_detectors() {
+        let rising = |index, value: f64| {
+            let result = BlurResult { name: "ShakeRisk".to_string(), value, threshold: 1.0, is_blurry: value > 1.0, higher_is_blurrier: true };
+            FrameReport { index, timestamp: None, results: vec![result], is_blurry: value > 1.0, export: None }
+        };
+        // A mean risk of 1.5 is above the threshold, so blurry; 0.5 is safe
+        assert!(aggregate(&[rising(0, 2.0), rising(1, 1.0)], FramePolicy::Mean, false));
+        assert!(!aggregate(&[rising(0, 0.2), rising(1, 0.8)], FramePolicy::Mean, false));
+    }
+
+    #[test]
+    fn orients_like_the_first_frame() {
+        let frame = ImageBuffer::from_fn(2, 1, |x, _| Luma([x as u8]));
+        // Orientation 6 is a quarter turn clockwise
+        let rotated = orient(frame.clone(), 6);
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 1)[0], 1);
+        assert_eq!(orient(frame.clone(), 1), frame);
+    }
+
+    #[test]
+    fn reads_every_tiff_page() {
+        let path = std::env::temp_dir().join(format!("grepfuzz-frames-{}.tiff", std::process::id()));
+        {
+            let mut encoder = tiff::encoder::TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+            for page in 0..3u8 {
+                let data: Vec<u8> = (0..64).map(|i| i as u8 * page).collect();
+                encoder.write_image::<tiff::encoder::colortype::Gray8>(8, 8, &data).unwrap();
+            }
+        }
+        let mut seen = Vec::new();
+        let count = visit_frames(&path, |i, img| seen.push((i, img.get_pixel(3, 0)[0]))).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(count, 3);
+        assert_eq!(seen, vec![(0, 0), (1, 3), (2, 6)]);
+    }
+}
@@ -0,0 +1,145 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::tile_analysis::TileSummary;
+
+/// Name of the NUL-separated manifest written next to the heatmaps.
+pub const MANIFEST_NAME: &str = "heatmaps.manifest";
+
+/// Maps a tile value to a color: red well below the threshold, yellow at it, green well above it.
+pub fn sharpness_color(value: f64, threshold: f64) -> Rgb<u8> {
+    // One step of the scale is a doubling of the value relative to the threshold
+    let t = if value <= 0.0 || threshold <= 0.0 {
+        if value > 0.0 { 1.0 } else { 0.0 }
+    } else {
+        (0.5 + 0.25 * (value / threshold).log2()).clamp(0.0, 1.0)
+    };
+    if t < 0.5 {
+        Rgb([255, (510.0 * t) as u8, 0])
+    } else {
+        Rgb([(510.0 * (1.0 - t)) as u8, 255, 0])
+    }
+}
+
+/// Renders a downscaled copy of `original` with each tile tinted by its sharpness.
+pub fn render_heatmap(original: &DynamicImage, summary: &TileSummary, threshold: f64, max_side: u32) -> RgbImage {
+    let base = if original.width().max(original.height()) > max_side {
+        original.thumbnail(max_side, max_side).to_rgb8()
+    } else {
+        original.to_rgb8()
+    };
+    let sx = base.width() as f64 / original.width().max(1) as f64;
+    let sy = base.height() as f64 / original.height().max(1) as f64;
+    let mut out = base.clone();
+    for tile in &summary.tiles {
+        let color = sharpness_color(tile.value, threshold);
+        let x0 = (tile.x as f64 * sx) as u32;
+        let y0 = (tile.y as f64 * sy) as u32;
+        let x1 = (((tile.x + tile.width) as f64 * sx).ceil() as u32).min(out.width());
+        let y1 = (((tile.y + tile.height) as f64 * sy).ceil() as u32).min(out.height());
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = base.get_pixel(x, y);
+                let border = x == x0 || y == y0;
+                let alpha = if border { 0.8 } else { 0.45 };
+                let blended = std::array::from_fn(|c| (p[c] as f64 * (1.0 - alpha) + color[c] as f64 * alpha) as u8);
+                out.put_pixel(x, y, Rgb(blended));
+            }
+        }
+    }
+    out
+}
+
+/// Stable file name for the heatmap of `input`, unique per input path.
+pub fn heatmap_file_name(input: &Path) -> String {
+    format!("{}.heatmap.png", file_tag(input))
+}
+
+/// `<stem>-<hash>` naming for files derived from `input`.
+pub fn file_tag(input: &Path) -> String {
+    // FNV-1a over the full path keeps same-named files from different directories apart. Raw bytes,
+    // so non-UTF-8 names that differ only in their invalid bytes still get different tags
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in input.as_os_str().as_encoded_bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    let stem = input.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "image".to_string());
+    format!("{}-{:08x}", stem, hash as u32)
+}
+
+/// Writes the heatmap PNG for `input` into `dir` and records it in the manifest.
+pub fn export_heatmap(
+    dir: &Path,
+    input: &Path,
+    original: &DynamicImage,
+    summary: &TileSummary,
+    threshold: f64,
+    max_side: u32,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create heatmap dir {}: {}", dir.display(), e))?;
+    let out_path = dir.join(heatmap_file_name(input));
+    render_heatmap(original, summary, threshold, max_side)
+        .save(&out_path)
+        .map_err(|e| format!("Failed to write heatmap {}: {}", out_path.display(), e))?;
+    update_manifest(dir, input, &out_path)
+        .map_err(|e| format!("Failed to update heatmap manifest: {}", e))?;
+    Ok(out_path)
+}
+
+/// Records `input\0heatmap\0` in the manifest in `dir`, replacing any earlier record for the same
+/// input so reruns do not pile up duplicates. Paths are written as raw bytes.
+pub fn update_manifest(dir: &Path, input: &Path, heatmap: &Path) -> io::Result<()> {
+    let path = dir.join(MANIFEST_NAME);
+    let existing = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let input = input.as_os_str().as_encoded_bytes();
+    let mut fields = existing.split(|&b| b == 0);
+    let mut out = Vec::with_capacity(existing.len());
+    while let (Some(name), Some(map)) = (fields.next(), fields.next()) {
+        if name != input {
+            for field in [name, map] {
+                out.extend_from_slice(field);
+                out.push(0);
+            }
+        }
+    }
+    for field in [input, heatmap.as_os_str().as_encoded_bytes()] {
+        out.extend_from_slice(field);
+        out.push(0);
+    }
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_NAME));
+    fs::write(&tmp, out)?;
+    fs::rename(&tmp, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn manifest_keeps_one_record_per_input() {
+        let dir = std::env::temp_dir().join(format!("grepfuzz-heatmap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (input, map) in [("a.jpg", "a-1.png"), ("b.jpg", "b-1.png"), ("a.jpg", "a-2.png")] {
+            update_manifest(&dir, Path::new(input), Path::new(map)).unwrap();
+        }
+        assert_eq!(fs::read(dir.join(MANIFEST_NAME)).unwrap(), b"b.jpg\0b-1.png\0a.jpg\0a-2.png\0");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn tags_tell_non_utf8_names_apart() {
+        // Both names decode lossily to "x\u{FFFD}.jpg"
+        let (a, b) = (Path::new(OsStr::from_bytes(b"x\xff.jpg")), Path::new(OsStr::from_bytes(b"x\xfe.jpg")));
+        assert_ne!(heatmap_file_name(a), heatmap_file_name(b));
+        assert_eq!(file_tag(Path::new("/photos/a.jpg")), file_tag(Path::new("/photos/a.jpg")));
+        assert!(sharpness_color(0.0, 1.0)[1] == 0 && sharpness_color(4.0, 1.0)[0] == 0);
+    }
+}
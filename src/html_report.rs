@@ -0,0 +1,271 @@
+use image::ImageFormat;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use crate::blur_result::ImageReport;
+use crate::detector_helpers::detector_key;
+
+/// What the HTML report shows for one analyzed image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportEntry {
+    pub path: String,
+    pub is_blurry: bool,
+    /// True if this run wrote the path to stdout.
+    pub selected: bool,
+    /// Detector name, value, threshold and verdict.
+    pub scores: Vec<(String, f64, f64, bool)>,
+    /// One-line facts from the optional passes (burst, duplicates, relative rank, combiner).
+    pub notes: Vec<String>,
+    pub exif: Vec<(&'static str, String)>,
+    pub heatmap: Option<PathBuf>,
+    /// JPEG thumbnail as a data URI; `None` if the file could not be decoded.
+    pub thumbnail: Option<String>,
+}
+
+impl ReportEntry {
+    /// Uses the thumbnail made during analysis; only reports rebuilt from the cache, which decoded
+    /// nothing, decode the file here.
+    pub fn new(path: &str, report: &ImageReport, selected: bool, thumbnail_size: u32) -> Self {
+        let mut notes = vec![format!("{}x{}, {} bytes", report.width, report.height, report.size)];
+        if let Some(p) = report.blur_probability {
+            notes.push(format!("combiner blur probability {:.3}", p));
+        }
+        if let Some(ref b) = report.burst {
+            notes.push(format!("burst {}: rank {} of {}{}", b.id, b.rank, b.size, if b.keep { ", kept" } else { "" }));
+        }
+        if let Some(ref d) = report.duplicate {
+            notes.push(format!("duplicate cluster {} of {}{}", d.cluster, d.size, if d.keeper { ", keeper" } else { "" }));
+        }
+        if let Some(ref r) = report.relative {
+            notes.push(format!("relative rank {} of {} ({:+.2} std){}", r.rank, r.size, r.deviation, if r.flagged { ", flagged" } else { "" }));
+        }
+        Self {
+            path: path.to_string(),
+            is_blurry: report.is_blurry,
+            selected,
+            scores: report.results.iter().map(|r| (r.name.clone(), r.value, r.threshold, r.is_blurry)).collect(),
+            notes,
+            exif: report.metadata.fields(),
+            heatmap: report.heatmap.clone(),
+            thumbnail: report.thumbnail.clone().or_else(|| {
+                let (img, _, _) = crate::image_loader::open_upright(Path::new(path)).ok()?;
+                thumbnail_data_uri(&img, thumbnail_size).ok()
+            }),
+        }
+    }
+}
+
+/// JPEG thumbnail of the upright `img`, at most `max_side` pixels, as a data URI.
+pub fn thumbnail_data_uri(img: &image::DynamicImage, max_side: u32) -> Result<String, String> {
+    let thumb = image::DynamicImage::ImageRgb8(img.thumbnail(max_side, max_side).to_rgb8());
+    let mut jpeg = Vec::new();
+    thumb.write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(format!("data:image/jpeg;base64,{}", base64(&jpeg)))
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `file://` URL of a path, absolute if it exists, with everything but unreserved characters
+/// and separators percent-encoded.
+fn file_url(path: &Path) -> String {
+    let abs = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut url = String::from("file://");
+    for &b in abs.to_string_lossy().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            url.push(b as char);
+        } else {
+            let _ = write!(url, "%{:02X}", b);
+        }
+    }
+    url
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em; background: #f4f4f4; }
+#controls { margin-bottom: 1em; }
+#grid { display: flex; flex-wrap: wrap; gap: 10px; }
+.card { width: 260px; background: white; border: 4px solid; border-radius: 4px; padding: 6px; font-size: 12px; }
+.card.blurry { border-color: #d33; }
+.card.sharp { border-color: #3a3; }
+.card.overridden { border-style: dashed; }
+.card img { display: block; max-width: 100%; max-height: 200px; margin: 0 auto; }
+.thumb-missing { height: 120px; display: flex; align-items: center; justify-content: center; color: #888; }
+.path { word-break: break-all; font-weight: bold; }
+.verdict { font-weight: bold; }
+.card.blurry .verdict { color: #d33; }
+.card.sharp .verdict { color: #3a3; }
+table { border-collapse: collapse; width: 100%; }
+td { padding: 1px 3px; }
+td.blurry { color: #d33; }
+td.sharp { color: #3a3; }
+.notes { color: #555; }
+";
+
+const SCRIPT: &str = "
+function sortCards() {
+  const key = document.getElementById('sort').value;
+  const dir = document.getElementById('dir').value === 'desc' ? -1 : 1;
+  const grid = document.getElementById('grid');
+  const cards = Array.from(grid.children);
+  cards.sort((a, b) => {
+    const x = a.getAttribute('data-' + key), y = b.getAttribute('data-' + key);
+    // Images without the score go last either way
+    if (x === null || y === null) return (x === null) - (y === null);
+    const nx = parseFloat(x), ny = parseFloat(y);
+    return dir * (isNaN(nx) || isNaN(ny) ? x.localeCompare(y) : nx - ny);
+  });
+  cards.forEach(c => grid.appendChild(c));
+}
+function flip(button) {
+  const card = button.closest('.card');
+  const blurry = !card.classList.contains('blurry');
+  card.classList.toggle('blurry', blurry);
+  card.classList.toggle('sharp', !blurry);
+  card.classList.toggle('overridden', card.getAttribute('data-original') !== (blurry ? '1' : '0'));
+  card.setAttribute('data-verdict', blurry ? '1' : '0');
+  card.querySelector('.verdict').textContent = blurry ? 'BLURRY' : 'SHARP';
+}
+function download() {
+  let csv = 'path,label\\n';
+  document.querySelectorAll('.card').forEach(c => {
+    const path = c.getAttribute('data-path');
+    const quoted = /[\",\\n]/.test(path) ? '\"' + path.replace(/\"/g, '\"\"') + '\"' : path;
+    csv += quoted + ',' + (c.classList.contains('blurry') ? 'blurry' : 'sharp') + '\\n';
+  });
+  const link = document.createElement('a');
+  link.href = URL.createObjectURL(new Blob([csv], { type: 'text/csv' }));
+  link.download = 'labels.csv';
+  link.click();
+}
+";
+
+/// Renders a self-contained page: one card per image with its thumbnail, verdict, scores and EXIF
+/// (as a tooltip), sortable by path, verdict or any detector. Reviewers can flip verdicts and
+/// download the result as a `path,label` manifest for `grepfuzz calibrate`, `eval` or `train`.
+pub fn render_report(entries: &[ReportEntry]) -> String {
+    let blurry = entries.iter().filter(|e| e.is_blurry).count();
+    let selected = entries.iter().filter(|e| e.selected).count();
+    let mut detectors: Vec<&str> = Vec::new();
+    for e in entries {
+        for (name, ..) in &e.scores {
+            if !detectors.contains(&name.as_str()) {
+                detectors.push(name);
+            }
+        }
+    }
+
+    let mut html = String::new();
+    let _ = write!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>grepfuzz report</title>\n<style>{}</style>\n<script>{}</script>\n</head>\n<body>\n", STYLE, SCRIPT);
+    let _ = writeln!(html, "<h1>grepfuzz report</h1>\n<p>{} images: {} blurry, {} sharp, {} selected</p>",
+        entries.len(), blurry, entries.len() - blurry, selected);
+    html.push_str("<div id=\"controls\">Sort by <select id=\"sort\" onchange=\"sortCards()\">\n<option value=\"index\">input order</option>\n<option value=\"path\">path</option>\n<option value=\"verdict\">verdict</option>\n");
+    for name in &detectors {
+        let _ = writeln!(html, "<option value=\"{}\">{}</option>", detector_key(name), escape_html(name));
+    }
+    html.push_str("</select>\n<select id=\"dir\" onchange=\"sortCards()\"><option value=\"asc\">ascending</option><option value=\"desc\">descending</option></select>\n<button onclick=\"download()\">Download labels</button></div>\n<div id=\"grid\">\n");
+
+    for (index, e) in entries.iter().enumerate() {
+        let class = if e.is_blurry { "blurry" } else { "sharp" };
+        let verdict = if e.is_blurry { "1" } else { "0" };
+        let tooltip: Vec<String> = e.exif.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+        let _ = write!(html, "<div class=\"card {}\" data-index=\"{}\" data-path=\"{}\" data-verdict=\"{}\" data-original=\"{}\"",
+            class, index, escape_html(&e.path), verdict, verdict);
+        for (name, value, ..) in &e.scores {
+            let _ = write!(html, " data-{}=\"{}\"", detector_key(name), value);
+        }
+        let _ = writeln!(html, " title=\"{}\">", escape_html(&tooltip.join("\n")));
+        match e.thumbnail {
+            Some(ref uri) => { let _ = writeln!(html, "<a href=\"{}\"><img src=\"{}\" alt=\"\"></a>", escape_html(&file_url(Path::new(&e.path))), uri); }
+            None => html.push_str("<div class=\"thumb-missing\">no preview</div>\n"),
+        }
+        let _ = writeln!(html, "<div class=\"path\">{}</div>", escape_html(&e.path));
+        let _ = writeln!(html, "<div><span class=\"verdict\">{}</span>{} <button onclick=\"flip(this)\">Flip</button></div>",
+            if e.is_blurry { "BLURRY" } else { "SHARP" }, if e.selected { " (selected)" } else { "" });
+        html.push_str("<table>\n");
+        for (name, value, threshold, is_blurry) in &e.scores {
+            let _ = writeln!(html, "<tr><td>{}</td><td class=\"{}\">{:.3}</td><td>/ {:.3}</td></tr>",
+                escape_html(name), if *is_blurry { "blurry" } else { "sharp" }, value, threshold);
+        }
+        html.push_str("</table>\n");
+        for note in &e.notes {
+            let _ = writeln!(html, "<div class=\"notes\">{}</div>", escape_html(note));
+        }
+        if let Some(ref heatmap) = e.heatmap {
+            let _ = writeln!(html, "<div><a href=\"{}\">heatmap</a></div>", escape_html(&file_url(heatmap)));
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+pub fn write_report(path: &Path, entries: &[ReportEntry]) -> Result<(), String> {
+    std::fs::write(path, render_report(entries)).map_err(|e| format!("Failed to write report {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn cards_carry_scores_and_escaped_text() {
+        let mut report = ImageReport { is_blurry: true, ..ImageReport::default() };
+        report.results.push(crate::blur_result::BlurResult { name: "OpenCV Laplacian".to_string(), value: 12.5, threshold: 55.0, is_blurry: true, higher_is_blurrier: false });
+        report.metadata.lens_model = Some("35mm <f/1.4>".to_string());
+        let entry = ReportEntry::new("/missing/a&b.jpg", &report, true, 64);
+        assert_eq!(entry.thumbnail, None);
+        let html = render_report(&[entry]);
+        assert!(html.contains("data-opencv_laplacian=\"12.5\""));
+        assert!(html.contains("data-path=\"/missing/a&amp;b.jpg\""));
+        assert!(html.contains("lens: 35mm &lt;f/1.4&gt;"));
+        assert!(html.contains("<option value=\"opencv_laplacian\">OpenCV Laplacian</option>"));
+    }
+
+    #[test]
+    fn entries_reuse_the_analysis_thumbnail() {
+        let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(8, 4, image::Luma([128])));
+        let uri = thumbnail_data_uri(&img, 4).unwrap();
+        assert!(uri.starts_with("data:image/jpeg;base64,"));
+        // The file is gone, so the thumbnail can only come from the report
+        let report = ImageReport { thumbnail: Some(uri.clone()), ..ImageReport::default() };
+        assert_eq!(ReportEntry::new("/missing/a.jpg", &report, false, 4).thumbnail, Some(uri));
+    }
+}
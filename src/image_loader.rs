@@ -1,5 +1,8 @@
-use image::{ImageBuffer, Luma, ImageReader};
-use std::io::{self, Read};
+use image::{DynamicImage, ImageBuffer, ImageDecoder, ImageFormat, ImageReader, ImageResult, Luma};
+use image::metadata::Orientation;
+use std::io::{self, BufRead, Cursor, Read, Seek};
+use std::path::Path;
+use crate::raw_preview::{self, RawPreview};
 
 #[derive(Clone)]
 pub enum ImageSource {
@@ -18,22 +21,64 @@ pub fn load_image(source: ImageSource) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>,
     }
 }
 
+/// Decodes an image and rotates/flips it upright according to its EXIF Orientation, so pixel
+/// coordinates match what viewers display. Returns the image and the EXIF orientation (1-8) applied.
+pub fn decode_upright<R: BufRead + Seek>(reader: ImageReader<R>) -> ImageResult<(DynamicImage, u8)> {
+    let mut decoder = reader.into_decoder()?;
+    // A broken Orientation tag should not make an otherwise readable image fail
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok((img, orientation.to_exif()))
+}
+
+/// Opens an image file upright; see [`decode_upright`]. Camera RAW files are not developed:
+/// their largest embedded JPEG preview is decoded instead and returned alongside the image.
+pub fn open_upright(path: &Path) -> Result<(DynamicImage, u8, Option<RawPreview>), String> {
+    if raw_preview::is_raw_path(path) {
+        let (img, orientation, preview) = open_raw_preview(path)?;
+        return Ok((img, orientation, Some(preview)));
+    }
+    let reader = ImageReader::open(path)
+        .map_err(|e| format!("Failed to open file {}: {}", path.display(), e))?
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to open file {}: {}", path.display(), e))?;
+    let (img, orientation) = decode_upright(reader)
+        .map_err(|e| format!("Failed to decode image {}: {}", path.display(), e))?;
+    Ok((img, orientation, None))
+}
+
+fn open_raw_preview(path: &Path) -> Result<(DynamicImage, u8, RawPreview), String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to open file {}: {}", path.display(), e))?;
+    let preview = raw_preview::find_largest_preview(&data)
+        .ok_or_else(|| format!("No embedded JPEG preview found in RAW file {}", path.display()))?;
+    let reader = ImageReader::with_format(Cursor::new(preview.bytes(&data)), ImageFormat::Jpeg);
+    let (mut img, mut orientation) = decode_upright(reader)
+        .map_err(|e| format!("Failed to decode embedded preview of {}: {}", path.display(), e))?;
+    // Previews rarely carry their own EXIF; the RAW container's orientation applies to them
+    if orientation == 1 {
+        if let Some(o) = raw_preview::raw_orientation(&data).and_then(Orientation::from_exif) {
+            img.apply_orientation(o);
+            orientation = o.to_exif();
+        }
+    }
+    Ok((img, orientation, preview))
+}
+
 impl ImageSource {
     pub fn from_file(filename: &str) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>, String> {
-        let img = ImageReader::open(filename)
-            .map_err(|e| format!("Failed to open file {}: {}", filename, e))?
-            .decode()
-            .map_err(|e| format!("Failed to decode image {}: {}", filename, e))?
-            .to_luma8();
-        Ok(img)
+        let (img, _, _) = open_upright(Path::new(filename))?;
+        Ok(img.to_luma8())
     }
     pub fn from_stdin_bytes() -> Result<ImageBuffer<Luma<u8>, Vec<u8>>, String> {
         let mut buf = Vec::new();
         io::stdin().read_to_end(&mut buf).map_err(|e| format!("Failed to read stdin: {}", e))?;
-        let img = image::load_from_memory(&buf)
-            .map_err(|e| format!("Failed to decode image from stdin: {}", e))?
-            .to_luma8();
-        Ok(img)
+        let reader = ImageReader::new(Cursor::new(buf))
+            .with_guessed_format()
+            .map_err(|e| format!("Failed to read stdin: {}", e))?;
+        let (img, _) = decode_upright(reader)
+            .map_err(|e| format!("Failed to decode image from stdin: {}", e))?;
+        Ok(img.to_luma8())
     }
     pub fn from_checkerboard(width: u32, height: u32) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>, String> {
         Ok(ImageBuffer::from_fn(width, height, |x, y| {
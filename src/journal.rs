@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::actions::{perform, ActionKind, Operation};
+use crate::blur_result::ImageReport;
+use crate::cli::UndoArgs;
+use crate::detector_helpers::detector_key;
+
+/// One line of the journal: a file action, or the undoing of one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub timestamp: String,
+    pub action: ActionKind,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Size and modification time (ns since the epoch) of the destination right after the action,
+    /// to tell whether it was changed since
+    pub size: Option<u64>,
+    pub modified: Option<i64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scores: BTreeMap<String, f64>,
+    /// Set on the entry recording that the latest earlier action with this action, source and
+    /// destination was undone
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undo: bool,
+}
+
+impl JournalEntry {
+    /// Entry for a completed operation, stamped now, with the destination's current state.
+    pub fn new(op: &Operation, report: Option<&ImageReport>) -> Self {
+        let meta = fs::symlink_metadata(&op.destination).ok();
+        let mut scores = BTreeMap::new();
+        if let Some(report) = report {
+            scores.extend(report.results.iter().map(|r| (detector_key(&r.name), r.value)));
+            if let Some(p) = report.blur_probability {
+                scores.insert("p_blurry".to_string(), p);
+            }
+        }
+        Self {
+            timestamp: crate::trash::local_timestamp(),
+            action: op.kind,
+            source: op.source.clone(),
+            destination: op.destination.clone(),
+            size: meta.as_ref().map(|m| m.len()),
+            modified: meta.as_ref().map(modified_ns),
+            scores,
+            undo: false,
+        }
+    }
+
+    fn key(&self) -> (ActionKind, &Path, &Path) {
+        (self.action, &self.source, &self.destination)
+    }
+}
+
+/// Append-only JSON-lines journal. Each entry is flushed to disk before the call returns, so a
+/// crash never loses the record of a file that was already moved.
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("Failed to open journal {}: {}", path.display(), e))?;
+        Ok(Self { file })
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| format!("Failed to encode journal entry: {}", e))?;
+        writeln!(self.file, "{}", line)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("Failed to write journal: {}", e))
+    }
+}
+
+pub fn read_journal(path: &Path) -> Result<Vec<JournalEntry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read journal {}: {}", path.display(), e))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e)))
+        .collect()
+}
+
+/// Actions of the journal that have not been undone yet, newest first. Each undo entry cancels the
+/// latest action before it with the same key, so a file moved again after an undo is pending again.
+pub fn pending(entries: &[JournalEntry]) -> Vec<&JournalEntry> {
+    let mut open: Vec<&JournalEntry> = Vec::new();
+    for entry in entries {
+        if !entry.undo {
+            open.push(entry);
+        } else if let Some(i) = open.iter().rposition(|e| e.key() == entry.key()) {
+            open.remove(i);
+        }
+    }
+    open.reverse();
+    open
+}
+
+/// Why `entry` cannot be undone safely, if it cannot. `force` accepts a destination that was
+/// modified since; a missing destination or an occupied source path is never overridden.
+pub fn conflict(entry: &JournalEntry, force: bool) -> Option<String> {
+    let Ok(meta) = fs::symlink_metadata(&entry.destination) else {
+        return Some(format!("{} no longer exists", entry.destination.display()));
+    };
+    if !force && (entry.size != Some(meta.len()) || entry.modified != Some(modified_ns(&meta))) {
+        return Some(format!("{} was modified since it was written", entry.destination.display()));
+    }
+    if matches!(entry.action, ActionKind::Move | ActionKind::Trash) && fs::symlink_metadata(&entry.source).is_ok() {
+        return Some(format!("{} exists again", entry.source.display()));
+    }
+    None
+}
+
+/// Reverses one action: moved and trashed files go back, copies and links are removed.
+pub fn reverse(entry: &JournalEntry) -> Result<(), String> {
+    match entry.action {
+        ActionKind::Move => perform(&Operation { kind: ActionKind::Move, source: entry.destination.clone(), destination: entry.source.clone() }).map(|_| ()),
+        ActionKind::Trash => {
+            if let Some(parent) = entry.source.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            let trashed = crate::trash::Trashed { file: entry.destination.clone(), info: crate::trash::info_path(&entry.destination) };
+            crate::trash::restore(&trashed, &entry.source)
+        }
+        ActionKind::Copy | ActionKind::HardLink | ActionKind::SymLink => fs::remove_file(&entry.destination)
+            .map_err(|e| format!("Failed to remove {}: {}", entry.destination.display(), e)),
+    }
+}
+
+/// `grepfuzz undo JOURNAL`: reverses every action not undone yet, newest first, and appends an
+/// undo entry for each to the same journal. Conflicting actions are skipped and reported.
+pub fn run(args: &UndoArgs) -> Result<(), String> {
+    let path = Path::new(&args.journal);
+    let entries = read_journal(path)?;
+    let mut journal = if args.dry_run { None } else { Some(Journal::open(path)?) };
+    let (mut undone, mut skipped) = (0, 0);
+    for entry in pending(&entries) {
+        if let Some(reason) = conflict(entry, args.force) {
+            eprintln!("Skipping {} {} -> {}: {}", entry.action.name(), entry.source.display(), entry.destination.display(), reason);
+            skipped += 1;
+            continue;
+        }
+        let Some(ref mut journal) = journal else {
+            eprintln!("would undo {} {} -> {}", entry.action.name(), entry.source.display(), entry.destination.display());
+            undone += 1;
+            continue;
+        };
+        if let Err(e) = reverse(entry) {
+            eprintln!("{}", e);
+            skipped += 1;
+            continue;
+        }
+        journal.append(&JournalEntry { timestamp: crate::trash::local_timestamp(), undo: true, ..entry.clone() })?;
+        undone += 1;
+    }
+    eprintln!("{} {} actions, {} skipped", if args.dry_run { "Would undo" } else { "Undid" }, undone, skipped);
+    if skipped > 0 {
+        return Err(format!("{} actions could not be undone; resolve them and run undo again", skipped));
+    }
+    Ok(())
+}
+
+fn modified_ns(meta: &fs::Metadata) -> i64 {
+    meta.modified().ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_pending_actions_and_detects_conflicts() {
+        let root = std::env::temp_dir().join(format!("grepfuzz-journal-{}", std::process::id()));
+        fs::create_dir_all(root.join("out")).unwrap();
+        let journal_path = root.join("journal.jsonl");
+        let mut journal = Journal::open(&journal_path).unwrap();
+        for (name, kind) in [("a.jpg", ActionKind::Move), ("b.jpg", ActionKind::Copy), ("c.jpg", ActionKind::Move)] {
+            fs::write(root.join(name), name).unwrap();
+            let op = Operation { kind, source: root.join(name), destination: root.join("out").join(name) };
+            perform(&op).unwrap();
+            journal.append(&JournalEntry::new(&op, None)).unwrap();
+        }
+        // c.jpg is edited after the move, so undoing it would lose the edit
+        fs::write(root.join("out/c.jpg"), "retouched").unwrap();
+
+        let args = UndoArgs { journal: journal_path.display().to_string(), dry_run: false, force: false };
+        assert!(run(&args).is_err());
+        assert_eq!(fs::read_to_string(root.join("a.jpg")).unwrap(), "a.jpg");
+        assert!(root.join("b.jpg").exists() && !root.join("out/b.jpg").exists());
+        assert!(!root.join("c.jpg").exists() && root.join("out/c.jpg").exists());
+
+        let entries = read_journal(&journal_path).unwrap();
+        assert_eq!(entries.iter().filter(|e| e.undo).count(), 2);
+        let left = pending(&entries);
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].source, root.join("c.jpg"));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn undo_pairs_with_the_latest_earlier_action() {
+        let root = std::env::temp_dir().join(format!("grepfuzz-journal-redo-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let journal_path = root.join("journal.jsonl");
+        let args = UndoArgs { journal: journal_path.display().to_string(), dry_run: false, force: false };
+        fs::write(root.join("a.jpg"), "a").unwrap();
+        let op = Operation { kind: ActionKind::Move, source: root.join("a.jpg"), destination: root.join("out/a.jpg") };
+        // move, undo, move again, undo again
+        for _ in 0..2 {
+            perform(&op).unwrap();
+            Journal::open(&journal_path).unwrap().append(&JournalEntry::new(&op, None)).unwrap();
+            assert_eq!(pending(&read_journal(&journal_path).unwrap()).len(), 1);
+            run(&args).unwrap();
+            assert!(root.join("a.jpg").exists() && !root.join("out/a.jpg").exists());
+            assert!(pending(&read_journal(&journal_path).unwrap()).is_empty());
+        }
+        assert_eq!(read_journal(&journal_path).unwrap().len(), 4);
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -11,74 +11,588 @@ pub mod blur_laplacian;
 pub mod blur_opencv;
 pub mod blur_result;
 pub mod blur_tenengrad;
+pub mod blur_shake;
+pub mod filter_expr;
 pub mod config;
 pub mod detector_helpers;
 pub mod output_helpers;
 pub mod image_source_helpers;
+pub mod tile_analysis;
+pub mod heatmap;
+pub mod roi;
+pub mod noise;
+pub mod exposure;
+pub mod raw_preview;
+pub mod frames;
+pub mod video;
+pub mod burst;
+pub mod dedup;
+pub mod relative;
+pub mod summary;
+pub mod html_report;
+pub mod xmp;
+pub mod trash;
+pub mod actions;
+pub mod journal;
+pub mod watch;
+pub mod cache;
+pub mod calibrate;
+pub mod combiner;
+pub mod eval;
 
 use std::path::Path;
 use crate::blur_detector::BlurDetector;
-use crate::blur_laplacian::LaplacianVarianceDetector;
-use crate::blur_tenengrad::TenengradDetector;
-use crate::blur_opencv::OpenCvLaplacianDetector;
-use crate::blur_result::BlurResult;
+use crate::blur_result::{BlurResult, ImageReport};
+use crate::config::GrepfuzzConfig;
+use crate::detector_helpers::detector_threshold;
 use image::{ImageBuffer, Luma};
 
 /// Processes an image at the given path using the provided blur detectors.
 pub fn process_image(
     path: &Path,
     detectors: &[Box<dyn BlurDetector>],
-) -> Result<(bool, Vec<BlurResult>, u64, u32, u32, Option<String>), Box<dyn std::error::Error>> {
+    config: &GrepfuzzConfig,
+) -> Result<ImageReport, Box<dyn std::error::Error>> {
+    if crate::video::is_video_path(path) {
+        return process_video(path, detectors, config);
+    }
     // Load image and convert to grayscale u8
-    let img = image::open(path)?.grayscale().to_luma8();
-    let width = img.width();
-    let height = img.height();
+    // Decode upright so tiles, regions and heatmaps use the displayed frame, not the sensor's
+    // RAW files are analyzed through their largest embedded JPEG preview
+    let (original, orientation, preview) = crate::image_loader::open_upright(path)?;
+    let img = original.grayscale().to_luma8();
+    let mut metadata = crate::metadata::extract_metadata(path);
+    if let (Some(p), true) = (preview, metadata == crate::metadata::ImageMetadata::default()) {
+        // Containers rexif cannot parse (RAF, CR3) still carry EXIF inside the preview itself;
+        // failing to read it again only costs the metadata
+        if let Ok(data) = p.read_from(path) {
+            metadata = crate::metadata::extract_metadata_from_buffer(&data);
+        }
+    }
+    let (mut report, heatmap_tiles) = analyze(&img, detectors, config, metadata.iso, config.heatmap.dir.is_some());
+    if config.frames.is_enabled() {
+        if let Err(e) = apply_frames(path, detectors, config, metadata.iso, orientation, &mut report) {
+            eprintln!("{}", e);
+        }
+    }
 
-    let mut results = Vec::new();
-    let mut all_blurry = true;
-    for det in detectors {
-        let (val, is_blurry) = det.detect(&img);
-        let name = det.name().to_string();
-        let threshold = if let Some(l) = det.as_any().downcast_ref::<LaplacianVarianceDetector>() {
-            l.threshold
-        } else if let Some(t) = det.as_any().downcast_ref::<TenengradDetector>() {
-            t.threshold
-        } else if let Some(o) = det.as_any().downcast_ref::<OpenCvLaplacianDetector>() {
-            o.threshold
-        } else { 0.0 };
-        results.push(BlurResult { name, value: val, threshold, is_blurry });
-        all_blurry = all_blurry && is_blurry;
+    if let Some(ref dir) = config.heatmap.dir {
+        match export_heatmap_for(path, &original, detectors, config, &report, heatmap_tiles, Path::new(dir)) {
+            Ok(heatmap) => report.heatmap = Some(heatmap),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if config.report.path.is_some() {
+        match crate::html_report::thumbnail_data_uri(&original, config.report.thumbnail_size.unwrap_or(256)) {
+            Ok(uri) => report.thumbnail = Some(uri),
+            Err(e) => eprintln!("{}: {}", path.display(), e),
+        }
     }
 
     // File size
-    let size = std::fs::metadata(path)?.len();
-    let focal = crate::metadata::extract_focal_length(path);
+    report.size = std::fs::metadata(path)?.len();
+    if config.shake.enabled.unwrap_or(false) {
+        apply_shake_risk(&img, &metadata, config, &mut report);
+    }
 
-    Ok((all_blurry, results, size, width, height, focal))
+    // Same "<value> mm" rendering rexif uses for the readable focal length
+    report.focal = metadata.focal_length.map(|f| format!("{} mm", f));
+    report.metadata = metadata;
+    report.orientation = orientation;
+    report.preview = preview;
+    apply_combiner(config, &mut report);
+
+    Ok(report)
+}
+
+/// Like [`process_image`], but reuses raw detector values from `cache` while the file and the
+/// scoring pipeline are unchanged. Thresholds are applied afresh on every run, so changing one
+/// reclassifies without decoding anything. Analyses the cache does not cover (tiles, regions, frames,
+/// heatmaps, noise, exposure metrics, video) bypass it.
+pub fn process_image_cached(
+    path: &Path,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    cache: Option<&crate::cache::ResultCache>,
+) -> Result<ImageReport, Box<dyn std::error::Error>> {
+    let cache = match cache {
+        Some(cache) if is_cacheable(path, config) => cache,
+        _ => return process_image(path, detectors, config),
+    };
+    // Relative paths from different working directories must not share entries
+    let cache_path = std::fs::canonicalize(path)?;
+    let key = cache.file_key(&cache_path, config.cache.content_hash.unwrap_or(false))?;
+    let pipeline = pipeline_signature(config);
+    let names: Vec<&str> = detectors.iter().map(|d| d.name()).collect();
+    let hash_kind = config.dedup.is_enabled().then(|| hash_kind_name(config));
+    if let Some(entry) = cache.lookup(&cache_path, &key, &names, &pipeline, hash_kind) {
+        return Ok(report_from_cache(detectors, config, entry, key.size));
+    }
+
+    let report = process_image(path, detectors, config)?;
+    let entry = crate::cache::CachedImage {
+        width: report.width,
+        height: report.height,
+        orientation: report.orientation,
+        preview: report.preview,
+        metadata: report.metadata.clone(),
+        // The first results are the pixel detectors, in order; pseudo-detectors follow
+        scores: report.results.iter().take(detectors.len()).map(|r| (r.name.clone(), r.value)).collect(),
+        hash: hash_kind.zip(report.hash).map(|(kind, hash)| (kind.to_string(), hash)),
+    };
+    if let Err(e) = cache.store(&cache_path, &key, &entry, &pipeline) {
+        eprintln!("{}", e);
+    }
+    Ok(report)
+}
+
+fn is_cacheable(path: &Path, config: &GrepfuzzConfig) -> bool {
+    !crate::video::is_video_path(path)
+        && !config.tiles.is_enabled()
+        && config.roi.regions.as_ref().is_none_or(|r| r.is_empty())
+        && !config.frames.is_enabled()
+        && config.heatmap.dir.is_none()
+        && !config.noise.is_enabled()
+        && !config.exposure.metrics.unwrap_or(false)
+}
+
+fn hash_kind_name(config: &GrepfuzzConfig) -> &'static str {
+    match config.dedup.hash.unwrap_or(crate::dedup::HashKind::Dhash) {
+        crate::dedup::HashKind::Dhash => "dhash",
+        crate::dedup::HashKind::Phash => "phash",
+    }
+}
+
+/// The preprocessing settings that change raw detector values; cached values are only reused
+/// under the same signature. Thresholds are deliberately absent.
+fn pipeline_signature(config: &GrepfuzzConfig) -> String {
+    let exposure = &config.exposure;
+    let normalize = match exposure.normalize {
+        Some(crate::exposure::Normalization::Stretch) => "stretch".to_string(),
+        Some(crate::exposure::Normalization::Clahe) => format!("clahe:{}", exposure.clahe_clip_limit.unwrap_or(2.0)),
+        Some(crate::exposure::Normalization::Gamma) => format!("gamma:{}", exposure.gamma.map_or("auto".to_string(), |g| g.to_string())),
+        Some(crate::exposure::Normalization::None) | None => "none".to_string(),
+    };
+    let center = if config.roi.center_weight.unwrap_or(false) {
+        config.roi.center_sigma.unwrap_or(0.35).to_string()
+    } else {
+        "off".to_string()
+    };
+    format!("normalize={};center_weight={}", normalize, center)
+}
+
+/// Rebuilds a report from cached raw values, classifying them with the current thresholds.
+fn report_from_cache(
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    entry: crate::cache::CachedImage,
+    size: u64,
+) -> ImageReport {
+    let results: Vec<BlurResult> = detectors.iter().zip(&entry.scores).map(|(det, (name, value))| BlurResult {
+        name: name.clone(),
+        value: *value,
+        threshold: detector_threshold(det.as_ref()),
+        is_blurry: crate::detector_helpers::classify(det.as_ref(), *value),
+        higher_is_blurrier: crate::detector_helpers::higher_is_blurrier(det.as_ref()),
+    }).collect();
+    let mut report = ImageReport {
+        is_blurry: results.iter().all(|r| r.is_blurry),
+        results,
+        size,
+        width: entry.width,
+        height: entry.height,
+        orientation: entry.orientation,
+        preview: entry.preview,
+        hash: entry.hash.map(|(_, hash)| hash),
+        ..ImageReport::default()
+    };
+    if config.shake.enabled.unwrap_or(false) {
+        // The shake-risk pseudo-detector never looks at pixels
+        apply_shake_risk(&ImageBuffer::new(0, 0), &entry.metadata, config, &mut report);
+    }
+    report.focal = entry.metadata.focal_length.map(|f| format!("{} mm", f));
+    report.metadata = entry.metadata;
+    apply_combiner(config, &mut report);
+    report
+}
+
+/// Samples a video file and analyzes each sampled frame like a still image.
+///
+/// The report carries every sampled frame with its timestamp; its own results are those of the
+/// sharpest frame (by [`burst::combined_score`] over every detector) and its verdict follows the frame policy, `any` by default.
+/// With an export directory configured, the top-k sharpest frames are written there as PNG.
+pub fn process_video(
+    path: &Path,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+) -> Result<ImageReport, Box<dyn std::error::Error>> {
+    let export_dir = config.video.export_dir.as_deref().map(Path::new);
+    let top_k = config.video.top_k.unwrap_or(3);
+    let mut frames = Vec::new();
+    // Sharpest frames kept for export, best first: (score, position in `frames`, pixels)
+    let mut best: Vec<(f64, usize, image::RgbImage)> = Vec::new();
+    let info = crate::video::sample_frames(path, config.video.sampling(), |index, timestamp, rgb| {
+        let luma = image::imageops::grayscale(&rgb);
+        let frame_report = analyze_luma(&luma, detectors, config, None);
+        let score = crate::burst::combined_score(&frame_report.results);
+        if export_dir.is_some() && top_k > 0 {
+            let pos = best.partition_point(|(s, _, _)| *s >= score);
+            if pos < top_k {
+                best.insert(pos, (score, frames.len(), rgb));
+                best.truncate(top_k);
+            }
+        }
+        frames.push(crate::frames::FrameReport {
+            index: index as usize,
+            timestamp: Some(timestamp),
+            results: frame_report.results,
+            is_blurry: frame_report.is_blurry,
+            export: None,
+        });
+    })?;
+    if frames.is_empty() {
+        return Err(format!("No frames could be decoded from {}", path.display()).into());
+    }
+
+    if let Some(dir) = export_dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let tag = crate::heatmap::file_tag(path);
+        for (_, pos, rgb) in &best {
+            let out = dir.join(format!("{}-f{:06}.png", tag, frames[*pos].index));
+            match rgb.save(&out) {
+                Ok(()) => frames[*pos].export = Some(out),
+                Err(e) => eprintln!("Failed to export frame to {}: {}", out.display(), e),
+            }
+        }
+    }
+
+    let sharpest = frames.iter()
+        .max_by(|a, b| crate::burst::combined_score(&a.results).total_cmp(&crate::burst::combined_score(&b.results)))
+        .map(|f| f.results.iter().map(|r| BlurResult { name: r.name.clone(), ..*r }).collect())
+        .unwrap_or_default();
+    Ok(ImageReport {
+        is_blurry: crate::frames::aggregate(&frames, config.frames.policy.unwrap_or(crate::frames::FramePolicy::Any), has_regional_verdict(config)),
+        results: sharpest,
+        size: std::fs::metadata(path)?.len(),
+        width: info.width,
+        height: info.height,
+        focal: None,
+        metadata: crate::metadata::ImageMetadata::default(),
+        orientation: 1,
+        preview: None,
+        frames,
+        tiles: Vec::new(),
+        rois: Vec::new(),
+        noise: None,
+        exposure: None,
+        heatmap: None,
+        burst: None,
+        hash: None,
+        duplicate: None,
+        relative: None,
+        blur_probability: None,
+        thumbnail: None,
+    })
 }
 
 /// Processes an in-memory image using the provided blur detectors. Used for stdin-bytes and synthetic modes.
 pub fn process_image_buffer(
     img: &ImageBuffer<Luma<u8>, Vec<u8>>,
     detectors: &[Box<dyn BlurDetector>],
-) -> (bool, Vec<BlurResult>, u64, u32, u32, Option<String>) {
-    let width = img.width();
-    let height = img.height();
+    config: &GrepfuzzConfig,
+) -> ImageReport {
+    // For in-memory images, size, focal and ISO are not available
+    let mut report = analyze_luma(img, detectors, config, None);
+    apply_combiner(config, &mut report);
+    report
+}
+
+/// Runs every detector over the whole frame (and over tiles and regions of interest, if
+/// configured) and combines the verdicts.
+fn analyze_luma(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    iso: Option<u32>,
+) -> ImageReport {
+    analyze(img, detectors, config, iso, false).0
+}
+
+/// [`analyze_luma`], plus, with `heatmap` set and tiling off, the first detector's tiles of the
+/// same preprocessed image and compensated scores the verdict came from.
+fn analyze(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    iso: Option<u32>,
+    heatmap: bool,
+) -> (ImageReport, Option<crate::tile_analysis::TileSummary>) {
+    let exposure = if config.exposure.metrics.unwrap_or(false) {
+        Some(crate::exposure::exposure_metrics(img))
+    } else {
+        None
+    };
+
+    // Hash the pixels as captured, so re-edited copies still match
+    let hash = config.dedup.is_enabled()
+        .then(|| crate::dedup::perceptual_hash(img, config.dedup.hash.unwrap_or(crate::dedup::HashKind::Dhash)));
+
+    // Normalization and center weighting happen before any detector sees the pixels
+    let normalized;
+    let img = match config.exposure.normalize {
+        Some(mode) if mode != crate::exposure::Normalization::None => {
+            normalized = crate::exposure::normalize(img, mode, config.exposure.gamma, config.exposure.clahe_clip_limit);
+            &normalized
+        }
+        _ => img,
+    };
+    let weighted;
+    let img = if config.roi.center_weight.unwrap_or(false) {
+        weighted = crate::roi::center_weight(img, config.roi.center_sigma.unwrap_or(0.35));
+        &weighted
+    } else {
+        img
+    };
+
+    let noise = if config.noise.is_enabled() {
+        Some(estimate_noise(img, detectors, config, iso))
+    } else {
+        None
+    };
+    // With compensation on, every later pass sees scores with the noise floor removed
+    let compensated: Vec<Box<dyn BlurDetector + '_>>;
+    let detectors: &[Box<dyn BlurDetector + '_>] = match noise {
+        Some(ref n) if n.compensated => {
+            compensated = detectors.iter().zip(&n.floors).map(|(det, (_, floor))| {
+                Box::new(crate::noise::NoiseCompensatedDetector { inner: det.as_ref(), floor: *floor }) as Box<dyn BlurDetector>
+            }).collect();
+            &compensated
+        }
+        _ => detectors,
+    };
+
+    let (results, frame_blurry) = run_detectors(img, detectors);
+    let mut all_blurry = frame_blurry;
+    let mut tiles = Vec::new();
+    if config.tiles.is_enabled() {
+        // A sharp subject anywhere in the frame outweighs a soft background
+        for det in detectors {
+            tiles.push(crate::tile_analysis::analyze_tiles(img, det.as_ref(), &config.tiles));
+        }
+        all_blurry = tiles.iter().all(|t| t.is_blurry);
+    }
+    let heatmap_tiles = detectors.first().filter(|_| heatmap && tiles.is_empty()).map(|det| {
+        let tile_config = crate::config::TileConfig { grid: Some(config.heatmap.grid.unwrap_or(8)), ..config.tiles.clone() };
+        crate::tile_analysis::analyze_tiles(img, det.as_ref(), &tile_config)
+    });
+
+    let mut rois = Vec::new();
+    for roi in config.roi.regions.iter().flatten() {
+        let crop = crate::roi::crop_roi(img, roi);
+        let (roi_results, roi_blurry) = run_detectors(&crop, detectors);
+        rois.push(crate::roi::RoiReport {
+            roi: *roi,
+            rect: roi.to_pixels(img.width(), img.height()),
+            results: roi_results,
+            is_blurry: roi_blurry,
+        });
+    }
+    if !rois.is_empty() {
+        // The regions replace the whole-frame verdict and any soft one rejects the image; with
+        // tiles as well, a frame without enough sharp tiles is still rejected
+        let tiles_blurry = !tiles.is_empty() && tiles.iter().all(|t| t.is_blurry);
+        all_blurry = rois.iter().any(|r| r.is_blurry) || tiles_blurry;
+    }
+
+    let report = ImageReport {
+        is_blurry: all_blurry,
+        results,
+        size: 0,
+        width: img.width(),
+        height: img.height(),
+        focal: None,
+        metadata: crate::metadata::ImageMetadata::default(),
+        orientation: 1,
+        preview: None,
+        frames: Vec::new(),
+        tiles,
+        rois,
+        noise,
+        exposure,
+        heatmap: None,
+        burst: None,
+        hash,
+        duplicate: None,
+        relative: None,
+        blur_probability: None,
+        thumbnail: None,
+    };
+    (report, heatmap_tiles)
+}
+
+/// Analyzes the remaining frames or pages of a multi-frame file and replaces the verdict with the
+/// configured frame policy. Frame 0 reuses the already computed report; single-frame files are left as is.
+/// Every frame is turned upright by the file's EXIF orientation, like frame 0.
+fn apply_frames(
+    path: &Path,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    iso: Option<u32>,
+    orientation: u8,
+    report: &mut ImageReport,
+) -> Result<(), String> {
+    let mut frames = vec![crate::frames::FrameReport {
+        index: 0,
+        timestamp: None,
+        results: report.results.iter().map(|r| BlurResult { name: r.name.clone(), ..*r }).collect(),
+        is_blurry: report.is_blurry,
+        export: None,
+    }];
+    let count = crate::frames::visit_frames(path, |index, frame| {
+        if index > 0 {
+            let frame_report = analyze_luma(&crate::frames::orient(frame, orientation), detectors, config, iso);
+            frames.push(crate::frames::FrameReport {
+                index,
+                timestamp: None,
+                results: frame_report.results,
+                is_blurry: frame_report.is_blurry,
+                export: None,
+            });
+        }
+    })?;
+    if count > 1 {
+        report.is_blurry = crate::frames::aggregate(&frames, config.frames.policy.unwrap_or(crate::frames::FramePolicy::All), has_regional_verdict(config));
+        report.frames = frames;
+    }
+    Ok(())
+}
+
+/// Whether tiles or regions, rather than the whole-frame detectors, decide a frame's verdict.
+fn has_regional_verdict(config: &GrepfuzzConfig) -> bool {
+    config.tiles.is_enabled() || config.roi.regions.as_ref().is_some_and(|r| !r.is_empty())
+}
+
+/// Replaces the verdict with the trained combiner's, if one is configured. The model sees the
+/// whole-frame results (shake risk included) and the metadata, so tile and region verdicts only
+/// reach it through the detector values.
+fn apply_combiner(config: &GrepfuzzConfig, report: &mut ImageReport) {
+    if let Some(ref model) = config.combiner.loaded {
+        let p = model.probability(&crate::combiner::features_of(report));
+        report.blur_probability = Some(p);
+        report.is_blurry = p >= model.threshold;
+    }
+}
+
+/// Adds the shake-risk pseudo-detector to the report. It only judges the capture settings, so it
+/// joins the whole-frame results and the verdict but not the per-tile or per-region passes.
+fn apply_shake_risk(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    metadata: &crate::metadata::ImageMetadata,
+    config: &GrepfuzzConfig,
+    report: &mut ImageReport,
+) {
+    let shake = &config.shake;
+    let det = match crate::blur_shake::ShakeRiskDetector::from_metadata(
+        metadata,
+        shake.threshold.unwrap_or(1.0),
+        shake.stabilization_stops.unwrap_or(0.0),
+        shake.crop_factor.unwrap_or(1.0),
+    ) {
+        Some(det) => det,
+        None => return,
+    };
+    let (value, is_blurry) = det.detect(img);
+    report.results.push(BlurResult { name: det.name().to_string(), value, threshold: det.threshold, is_blurry, higher_is_blurrier: true });
+    if shake.include_in_verdict.unwrap_or(true) {
+        // A shot too slow to hold steady is flagged even when its pixels pass; a safe shutter
+        // speed never clears pixels that look blurry
+        report.is_blurry = report.is_blurry || is_blurry;
+    }
+}
+
+/// Estimates image noise (optionally blended with the ISO prior) and each detector's noise floor.
+fn estimate_noise(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    iso: Option<u32>,
+) -> crate::noise::NoiseReport {
+    let measured_sigma = crate::noise::estimate_noise_sigma(img);
+    let sigma = match iso {
+        Some(iso) if config.noise.iso_prior.unwrap_or(true) => {
+            let prior = crate::noise::iso_prior_sigma(iso, config.noise.iso_base_sigma.unwrap_or(0.8));
+            crate::noise::combine_with_prior(measured_sigma, prior)
+        }
+        _ => measured_sigma,
+    };
+    let floors = detectors.iter()
+        .map(|det| (det.name().to_string(), crate::noise::noise_floor(det.as_ref(), sigma)))
+        .collect();
+    crate::noise::NoiseReport {
+        measured_sigma,
+        iso,
+        sigma,
+        floors,
+        compensated: config.noise.compensate.unwrap_or(false),
+    }
+}
+
+/// Runs each detector once; returns the results and whether all of them call the image blurry.
+fn run_detectors(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    detectors: &[Box<dyn BlurDetector + '_>],
+) -> (Vec<BlurResult>, bool) {
     let mut results = Vec::new();
     let mut all_blurry = true;
     for det in detectors {
         let (val, is_blurry) = det.detect(img);
         let name = det.name().to_string();
-        let threshold = if let Some(l) = det.as_any().downcast_ref::<LaplacianVarianceDetector>() {
-            l.threshold
-        } else if let Some(t) = det.as_any().downcast_ref::<TenengradDetector>() {
-            t.threshold
-        } else if let Some(o) = det.as_any().downcast_ref::<OpenCvLaplacianDetector>() {
-            o.threshold
-        } else { 0.0 };
-        results.push(BlurResult { name, value: val, threshold, is_blurry });
+        let threshold = detector_threshold(det.as_ref());
+        results.push(BlurResult { name, value: val, threshold, is_blurry, higher_is_blurrier: crate::detector_helpers::higher_is_blurrier(det.as_ref()) });
         all_blurry = all_blurry && is_blurry;
     }
-    // For in-memory images, size and focal are not available
-    (all_blurry, results, 0, width, height, None)
+    (results, all_blurry)
+}
+
+/// Exports a heatmap from the first detector's tiles, or from `heatmap_tiles`, tiled just for the
+/// heatmap, if analysis was untiled.
+fn export_heatmap_for(
+    path: &Path,
+    original: &image::DynamicImage,
+    detectors: &[Box<dyn BlurDetector>],
+    config: &GrepfuzzConfig,
+    report: &ImageReport,
+    heatmap_tiles: Option<crate::tile_analysis::TileSummary>,
+    dir: &Path,
+) -> Result<std::path::PathBuf, String> {
+    let det = detectors.first().ok_or("No detectors configured for heatmap")?;
+    let summary = report.tiles.first().cloned().or(heatmap_tiles).ok_or("No tiles to draw a heatmap from")?;
+    let max_side = config.heatmap.max_size.unwrap_or(512);
+    crate::heatmap::export_heatmap(dir, path, original, &summary, detector_threshold(det.as_ref()), max_side)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_and_tiles_both_reject() {
+        // Sharp checkerboard on the left half, flat gray on the right
+        let img = ImageBuffer::from_fn(64, 64, |x, y| Luma([if x < 32 && (x + y) % 2 == 0 { 255 } else { 128 }]));
+        let detectors: Vec<Box<dyn BlurDetector>> = vec![Box::new(crate::blur_laplacian::LaplacianVarianceDetector::new(0.1))];
+        let region = |spec: &str| -> crate::roi::Roi { spec.parse().unwrap() };
+        let mut config = GrepfuzzConfig::default();
+        config.tiles.grid = Some(2);
+        config.roi.regions = Some(vec![region("0,0,0.5,1")]);
+        // A sharp region with half the tiles sharp is kept
+        assert!(!process_image_buffer(&img, &detectors, &config).is_blurry);
+        // Too few sharp tiles reject it even though the region is sharp
+        config.tiles.min_sharp_fraction = Some(1.0);
+        assert!(process_image_buffer(&img, &detectors, &config).is_blurry);
+        // A soft region rejects it even though enough tiles are sharp
+        config.tiles.min_sharp_fraction = None;
+        config.roi.regions = Some(vec![region("0.5,0,0.5,1")]);
+        assert!(process_image_buffer(&img, &detectors, &config).is_blurry);
+    }
 }
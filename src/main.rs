@@ -1,4 +1,4 @@
-use grepfuzz::process_image;
+use grepfuzz::process_image_cached;
 
 use grepfuzz::image_loader::{ImageInputMode, analyze_image_input};
 use grepfuzz::detector_helpers;
@@ -16,10 +16,11 @@ use std::path::Path;
 
 
 
+use grepfuzz::blur_result::ImageReport;
 use grepfuzz::config::GrepfuzzConfig;
 
 
-use grepfuzz::cli::Cli;
+use grepfuzz::cli::{Cli, Command, WatchArgs};
 
 fn main() -> io::Result<()> {
     let cli = Cli::parse();
@@ -27,9 +28,30 @@ fn main() -> io::Result<()> {
 
     // Load config (now merged with CLI overrides)
     let config = GrepfuzzConfig::from_cli(&cli);
-    let laplacian_threshold = config.detectors.laplacian_threshold.unwrap_or(0.1);
-    let tenengrad_threshold = config.detectors.tenengrad_threshold.unwrap_or(1000.0);
-    let opencv_laplacian_threshold = config.detectors.opencv_laplacian_threshold.unwrap_or(0.1);
+    if let Some(ref command) = cli.command {
+        let result = match command {
+            Command::Calibrate(args) => grepfuzz::calibrate::run(args, &config, cli.config.as_deref()),
+            Command::Eval(args) => grepfuzz::eval::run(args, &config),
+            Command::Train(args) => grepfuzz::combiner::run(args, &config),
+            Command::Undo(args) => grepfuzz::journal::run(args),
+            Command::Watch(args) => run_watch(args, &cli, &config),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let (laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold) = detector_helpers::configured_thresholds(&config);
+    let cache = open_cache(&config);
+    let mut actions = match grepfuzz::actions::FileActions::from_config(&config.actions) {
+        Ok(actions) => actions,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let blur_mode = cli.blur || (!cli.blur && !cli.sharp); // default to blur if neither specified
 
     // Unified image input handling
     // use grepfuzz::image_loader::{analyze_image_input, ImageInputMode}; // Already imported at top
@@ -60,8 +82,8 @@ fn main() -> io::Result<()> {
     // Handle verbose output for StdinBytes mode
     if let (Some(ImageInputMode::StdinBytes), Some(_source), Some(img)) = (input_mode_val.clone(), Some(source.clone()), img_opt.clone()) {
         let detectors = detector_helpers::build_detectors(laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold);
-        let (is_blurry, _results, _size, _width, _height, _focal) = grepfuzz::process_image_buffer(&img, detectors.as_slice());
-        if is_blurry {
+        let report = grepfuzz::process_image_buffer(&img, detectors.as_slice(), &config);
+        if report.is_blurry {
             stdout.write_all(&img)?;
         }
         return Ok(());
@@ -84,21 +106,30 @@ fn main() -> io::Result<()> {
         let path = std::path::Path::new(&filename);
         let detectors = detector_helpers::build_detectors(laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold);
 
-        match process_image(path, detectors.as_slice()) {
-            Ok((is_blurry, results, size, width, height, focal)) => {
-                output_helpers::print_results(
-                    &mut stdout,
-                    is_blurry,
-                    results.as_slice(),
-                    size,
-                    width,
-                    height,
-                    &focal,
-                    filename,
-                    cli.verbose,
-                    cli.ascii,
-                )?;
-                
+        match process_image_cached(path, detectors.as_slice(), &config, cache.as_ref()) {
+            Ok(report) => {
+                // Same selection as the stdin loop; only the output depends on it
+                let selected = blur_mode == report.is_blurry && config.filter_matches(&report, filename);
+                if selected {
+                    output_helpers::print_results(
+                        &mut stdout,
+                        &report,
+                        filename,
+                        cli.verbose,
+                        cli.ascii,
+                        &config,
+                    )?;
+                }
+                if config.xmp.is_enabled() {
+                    write_sidecar(path, &report, report.is_blurry, &config);
+                }
+                if let Some(ref out) = config.report.path {
+                    let entry = grepfuzz::html_report::ReportEntry::new(filename, &report, selected, config.report.thumbnail_size.unwrap_or(256));
+                    if let Err(e) = grepfuzz::html_report::write_report(Path::new(out), &[entry]) {
+                        eprintln!("{}", e);
+                    }
+                }
+                apply_action(&mut actions, path, &report, selected);
             }
             Err(e) => {
                 eprintln!("Error processing {}: {}", filename, e);
@@ -128,7 +159,12 @@ fn main() -> io::Result<()> {
     // Otherwise, process stdin as before
     let mut reader = stdin.lock();
     let mut buffer = Vec::new();
-    let blur_mode = cli.blur || (!cli.blur && !cli.sharp); // default to blur if neither specified
+    // Burst, dedup and relative modes need every report before they can select, so output waits for the end of input
+    let batch = config.bursts.is_enabled() || config.dedup.is_enabled() || config.relative.is_enabled();
+    let mut collected = Vec::new();
+    let mut summary = config.summary.is_enabled().then(grepfuzz::summary::BatchSummary::default);
+    let mut report_entries = config.report.path.is_some().then(Vec::new);
+    let thumbnail_size = config.report.thumbnail_size.unwrap_or(256);
     // ... (rest unchanged)
     loop {
         buffer.clear();
@@ -141,34 +177,206 @@ fn main() -> io::Result<()> {
         }
         let path_str = match String::from_utf8(buffer.clone()) {
             Ok(s) => s,
-            Err(_) => continue,
+            Err(_) => {
+                if let Some(ref mut s) = summary {
+                    s.skipped += 1;
+                }
+                continue;
+            }
         };
         let path = Path::new(&path_str);
         // Recreate detectors for each file if needed (or reuse from above)
-        let mut detectors: Vec<Box<dyn grepfuzz::blur_detector::BlurDetector>> = Vec::new();
-        detectors.push(Box::new(grepfuzz::blur_laplacian::LaplacianVarianceDetector::new(laplacian_threshold)));
-        detectors.push(Box::new(grepfuzz::blur_tenengrad::TenengradDetector::new(tenengrad_threshold)));
-        detectors.push(Box::new(grepfuzz::blur_opencv::OpenCvLaplacianDetector::new(opencv_laplacian_threshold)));
-        match process_image(path, detectors.as_slice()) {
-            Ok((is_blurry, results, size, width, height, _focal)) => {
-                if (blur_mode && is_blurry) || (!blur_mode && !is_blurry) {
-                    if cli.ascii {
-                        // Print all detector results in ASCII/TSV style
-for res in &results {
-    println!("{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.3}\t{}", path.display(), size, width, height, res.name, res.value, res.threshold, if res.is_blurry { "BLURRY" } else { "SHARP" });
-}
-
-                    } else {
-                        stdout.write_all(path_str.as_bytes())?;
-                        stdout.write_all(&[0])?;
+        let detectors = detector_helpers::build_detectors(laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold);
+        let result = process_image_cached(path, detectors.as_slice(), &config, cache.as_ref());
+        if let Some(ref mut s) = summary {
+            match result {
+                Ok(ref report) => s.record(report),
+                Err(_) => s.errors += 1,
+            }
+        }
+        match result {
+            Ok(report) if batch => collected.push((path_str, report)),
+            Ok(report) => {
+                let selected = blur_mode == report.is_blurry && config.filter_matches(&report, &path_str);
+                if selected {
+                    emit_path(&mut stdout, &report, &path_str, cli.ascii, &config)?;
+                    if let Some(ref mut s) = summary {
+                        s.emitted += 1;
                     }
                 }
+                if let Some(ref mut entries) = report_entries {
+                    entries.push(grepfuzz::html_report::ReportEntry::new(&path_str, &report, selected, thumbnail_size));
+                }
+                if config.xmp.is_enabled() {
+                    write_sidecar(path, &report, report.is_blurry, &config);
+                }
+                apply_action(&mut actions, path, &report, selected);
             }
             Err(e) => {
                 eprintln!("Error processing {}: {}", path_str, e);
             }
         }
     }
+
+    if batch {
+        let reports: Vec<&ImageReport> = collected.iter().map(|(_, report)| report).collect();
+        let mut bursts = config.bursts.is_enabled()
+            .then(|| grepfuzz::burst::assign_bursts(&reports, config.bursts.gap_seconds.unwrap_or(1.0), config.bursts.keep.unwrap_or(1)))
+            .map(Vec::into_iter);
+        let mut duplicates = config.dedup.is_enabled()
+            .then(|| grepfuzz::dedup::cluster_duplicates(&reports, config.dedup.max_distance.unwrap_or(10)))
+            .map(Vec::into_iter);
+        let mut relative = config.relative.is_enabled().then(|| {
+            let paths: Vec<&str> = collected.iter().map(|(path_str, _)| path_str.as_str()).collect();
+            let group = config.relative.group.unwrap_or(grepfuzz::relative::RelativeGroup::Directory);
+            let groups = grepfuzz::relative::group_ids(&paths, &reports, group, config.bursts.gap_seconds.unwrap_or(1.0));
+            let scores: Vec<f64> = reports.iter().map(|r| grepfuzz::relative::relative_score(r)).collect();
+            grepfuzz::relative::assign_relative(&scores, &groups, config.relative.bottom_percent, config.relative.below_median_std).into_iter()
+        });
+        for (path_str, mut report) in collected {
+            report.burst = bursts.as_mut().and_then(Iterator::next);
+            report.duplicate = duplicates.as_mut().and_then(Iterator::next);
+            report.relative = relative.as_mut().and_then(Iterator::next);
+            // A keeper has to survive every enabled grouping. Blur mode emits the rejects, sharp mode the keepers
+            let keep = report.burst.as_ref().is_none_or(|b| b.keep)
+                && report.duplicate.as_ref().is_none_or(|d| d.keeper)
+                && report.relative.as_ref().is_none_or(|r| !r.flagged);
+            let selected = blur_mode != keep && config.filter_matches(&report, &path_str);
+            if selected {
+                emit_path(&mut stdout, &report, &path_str, cli.ascii, &config)?;
+                if let Some(ref mut s) = summary {
+                    s.emitted += 1;
+                }
+            }
+            if let Some(ref mut entries) = report_entries {
+                entries.push(grepfuzz::html_report::ReportEntry::new(&path_str, &report, selected, thumbnail_size));
+            }
+            if config.xmp.is_enabled() {
+                write_sidecar(Path::new(&path_str), &report, !keep, &config);
+            }
+            apply_action(&mut actions, Path::new(&path_str), &report, selected);
+        }
+    }
+
+    if let (Some(entries), Some(path)) = (report_entries, config.report.path.as_ref()) {
+        if let Err(e) = grepfuzz::html_report::write_report(Path::new(path), &entries) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(s) = summary {
+        write_summary(&s, &config);
+    }
     Ok(())
 }
 
+/// `grepfuzz watch DIR`: analyzes images as they finish landing in the directory and handles each
+/// like a path read from stdin (output, XMP sidecar, file action) until SIGINT or SIGTERM.
+fn run_watch(args: &WatchArgs, cli: &Cli, config: &GrepfuzzConfig) -> Result<(), String> {
+    let (laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold) = detector_helpers::configured_thresholds(config);
+    let cache = open_cache(config);
+    let mut actions = grepfuzz::actions::FileActions::from_config(&config.actions)?;
+    let mut summary = config.summary.is_enabled().then(grepfuzz::summary::BatchSummary::default);
+    let options = grepfuzz::watch::WatchOptions {
+        recursive: args.recursive,
+        debounce: std::time::Duration::from_millis(args.debounce.unwrap_or(500)),
+        backlog: !args.no_backlog,
+        ignore: actions.as_ref().map_or(Vec::new(), |a| a.destinations()),
+    };
+    let stop = grepfuzz::watch::stop_on_signal()?;
+    let mut stdout = io::stdout();
+    let blur_mode = cli.blur || (!cli.blur && !cli.sharp); // default to blur if neither specified
+    eprintln!("Watching {} (Ctrl-C to stop)", args.dir);
+    grepfuzz::watch::watch(Path::new(&args.dir), &options, &stop, |path| {
+        let Some(path_str) = path.to_str() else {
+            if let Some(ref mut s) = summary {
+                s.skipped += 1;
+            }
+            return;
+        };
+        let detectors = detector_helpers::build_detectors(laplacian_threshold, tenengrad_threshold, opencv_laplacian_threshold);
+        let report = match process_image_cached(path, detectors.as_slice(), config, cache.as_ref()) {
+            Ok(report) => report,
+            Err(e) => {
+                if let Some(ref mut s) = summary {
+                    s.errors += 1;
+                }
+                eprintln!("Error processing {}: {}", path_str, e);
+                return;
+            }
+        };
+        if let Some(ref mut s) = summary {
+            s.record(&report);
+        }
+        let selected = blur_mode == report.is_blurry && config.filter_matches(&report, path_str);
+        if selected {
+            // Flushed per image, so whoever reads the output hears about a soft shot right away
+            if let Err(e) = emit_path(&mut stdout, &report, path_str, cli.ascii, config).and_then(|_| stdout.flush()) {
+                eprintln!("Failed to write output: {}", e);
+            }
+            if let Some(ref mut s) = summary {
+                s.emitted += 1;
+            }
+        }
+        if config.xmp.is_enabled() {
+            write_sidecar(path, &report, report.is_blurry, config);
+        }
+        apply_action(&mut actions, path, &report, selected);
+    })?;
+    if let Some(s) = summary {
+        write_summary(&s, config);
+    }
+    Ok(())
+}
+
+/// The configured result cache; a cache that fails to open is reported and skipped.
+fn open_cache(config: &GrepfuzzConfig) -> Option<grepfuzz::cache::ResultCache> {
+    config.cache.path.as_ref().and_then(|p| match grepfuzz::cache::ResultCache::open(Path::new(p)) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            eprintln!("{}. Continuing without cache.", e);
+            None
+        }
+    })
+}
+
+/// Writes the batch summary to the configured file, or to stderr.
+fn write_summary(summary: &grepfuzz::summary::BatchSummary, config: &GrepfuzzConfig) {
+    let text = summary.render(config.summary.bins.unwrap_or(10));
+    match config.summary.path {
+        Some(ref path) => {
+            if let Err(e) = std::fs::write(path, text) {
+                eprintln!("Failed to write summary to {}: {}", path, e);
+            }
+        }
+        None => eprint!("{}", text),
+    }
+}
+
+/// Records the verdict in the image's XMP sidecar; failures are reported and do not stop the run.
+fn write_sidecar(path: &Path, report: &ImageReport, rejected: bool, config: &GrepfuzzConfig) {
+    if let Err(e) = grepfuzz::xmp::write_sidecar(path, report, rejected, &config.xmp) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Moves, copies, links or trashes the image once everything else is done with it; failures are
+/// reported and do not stop the run.
+fn apply_action(actions: &mut Option<grepfuzz::actions::FileActions>, path: &Path, report: &ImageReport, selected: bool) {
+    if let Some(actions) = actions {
+        if let Err(e) = actions.apply(path, report, selected) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// Writes a selected path: all detector rows in `--ascii` mode, otherwise the NUL-terminated path.
+fn emit_path<W: Write>(stdout: &mut W, report: &ImageReport, path_str: &str, ascii: bool, config: &GrepfuzzConfig) -> io::Result<()> {
+    if ascii {
+        // Print all detector results in ASCII/TSV style
+        output_helpers::print_ascii_rows(stdout, report, path_str, config)
+    } else {
+        stdout.write_all(path_str.as_bytes())?;
+        stdout.write_all(&[0])
+    }
+}
+
@@ -1,12 +1,257 @@
 use std::path::Path;
-use rexif::{parse_file, ExifTag};
+use serde::{Deserialize, Serialize};
+use rexif::{parse_buffer, parse_file, ExifResult, ExifTag, TagValue};
 
-pub fn extract_focal_length(path: &Path) -> Option<String> {
-    let exif = parse_file(path).ok()?;
-    for entry in exif.entries {
-        if entry.tag == ExifTag::FocalLength {
-            return Some(entry.value_more_readable.to_string());
+/// EXIF BodySerialNumber, which rexif does not know by name.
+const TAG_BODY_SERIAL_NUMBER: u16 = 0xa431;
+
+/// Typed EXIF metadata for one image. Every field is `None` when the tag is absent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    /// Exposure time in seconds.
+    pub exposure_time: Option<f64>,
+    /// Aperture as an f-number.
+    pub aperture: Option<f64>,
+    pub iso: Option<u32>,
+    /// Focal length in mm.
+    pub focal_length: Option<f64>,
+    /// 35mm-equivalent focal length in mm.
+    pub focal_length_35mm: Option<f64>,
+    pub lens_model: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub serial_number: Option<String>,
+    /// Capture time as recorded by the camera ("YYYY:MM:DD HH:MM:SS").
+    pub capture_time: Option<String>,
+    /// EXIF Orientation (1-8).
+    pub orientation: Option<u16>,
+    /// Latitude in decimal degrees, negative south of the equator.
+    pub gps_latitude: Option<f64>,
+    /// Longitude in decimal degrees, negative west of Greenwich.
+    pub gps_longitude: Option<f64>,
+    /// Altitude in meters, negative below sea level.
+    pub gps_altitude: Option<f64>,
+}
+
+impl ImageMetadata {
+    /// All present fields as (key, value) pairs, in a fixed order. Keys are stable and are
+    /// used as column names by every output format.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let num = |v: Option<f64>| v.map(|v| v.to_string());
+        // Keep text values safe for tab/NUL-separated output
+        let text = |v: &Option<String>| v.as_ref().map(|s| s.chars().map(|c| if c.is_control() { ' ' } else { c }).collect());
+        [
+            ("exposure_time", num(self.exposure_time)),
+            ("aperture", num(self.aperture)),
+            ("iso", self.iso.map(|v| v.to_string())),
+            ("focal_length", num(self.focal_length)),
+            ("focal_length_35mm", num(self.focal_length_35mm)),
+            ("lens", text(&self.lens_model)),
+            ("make", text(&self.camera_make)),
+            ("camera", text(&self.camera_model)),
+            ("serial", text(&self.serial_number)),
+            ("capture_time", text(&self.capture_time)),
+            ("orientation", self.orientation.map(|v| v.to_string())),
+            ("gps_latitude", num(self.gps_latitude)),
+            ("gps_longitude", num(self.gps_longitude)),
+            ("gps_altitude", num(self.gps_altitude)),
+        ]
+        .into_iter()
+        .filter_map(|(key, v)| v.map(|v| (key, v)))
+        .collect()
+    }
+}
+
+fn first_rational(value: &TagValue) -> Option<f64> {
+    match value {
+        TagValue::URational(v) => v.first().filter(|r| r.denominator != 0).map(|r| r.value()),
+        TagValue::IRational(v) => v.first().filter(|r| r.denominator != 0).map(|r| r.value()),
+        _ => None,
+    }
+}
+
+fn first_integer(value: &TagValue) -> Option<u32> {
+    match value {
+        TagValue::U16(v) => v.first().map(|&n| n as u32),
+        TagValue::U32(v) => v.first().copied(),
+        _ => None,
+    }
+}
+
+fn ascii(value: &TagValue) -> Option<String> {
+    match value {
+        TagValue::Ascii(s) => {
+            let s = s.trim_matches(char::from(0)).trim();
+            if s.is_empty() { None } else { Some(s.to_string()) }
+        }
+        _ => None,
+    }
+}
+
+/// Converts a degrees/minutes/seconds triplet to decimal degrees.
+fn dms_to_degrees(value: &TagValue) -> Option<f64> {
+    match value {
+        TagValue::URational(v) if v.len() == 3 && v.iter().all(|r| r.denominator != 0) => {
+            Some(v[0].value() + v[1].value() / 60.0 + v[2].value() / 3600.0)
+        }
+        _ => None,
+    }
+}
+
+/// Reads every supported EXIF field from `path`. Files without EXIF yield an empty struct.
+pub fn extract_metadata(path: &Path) -> ImageMetadata {
+    metadata_from(parse_file(path))
+}
+
+/// Like [`extract_metadata`], for an in-memory JPEG or TIFF (e.g. a RAW file's embedded preview).
+pub fn extract_metadata_from_buffer(data: &[u8]) -> ImageMetadata {
+    metadata_from(parse_buffer(data))
+}
+
+fn metadata_from(parsed: ExifResult) -> ImageMetadata {
+    let mut meta = ImageMetadata::default();
+    let exif = match parsed {
+        Ok(exif) => exif,
+        Err(_) => return meta,
+    };
+    let (mut lat_ref, mut lon_ref, mut alt_ref) = (None, None, None);
+    for entry in &exif.entries {
+        let v = &entry.value;
+        match entry.tag {
+            ExifTag::ExposureTime => meta.exposure_time = first_rational(v),
+            ExifTag::FNumber => meta.aperture = first_rational(v),
+            ExifTag::ISOSpeedRatings => meta.iso = first_integer(v),
+            ExifTag::FocalLength => meta.focal_length = first_rational(v),
+            ExifTag::FocalLengthIn35mmFilm => meta.focal_length_35mm = first_integer(v).filter(|&f| f > 0).map(|f| f as f64),
+            ExifTag::LensModel => meta.lens_model = ascii(v),
+            ExifTag::Make => meta.camera_make = ascii(v),
+            ExifTag::Model => meta.camera_model = ascii(v),
+            ExifTag::DateTimeOriginal => meta.capture_time = ascii(v),
+            // DateTime (IFD0) is only a fallback; DateTimeOriginal (Exif IFD) overrides it
+            ExifTag::DateTime if meta.capture_time.is_none() => meta.capture_time = ascii(v),
+            ExifTag::Orientation => meta.orientation = first_integer(v).map(|o| o as u16),
+            ExifTag::GPSLatitude => meta.gps_latitude = dms_to_degrees(v),
+            ExifTag::GPSLongitude => meta.gps_longitude = dms_to_degrees(v),
+            ExifTag::GPSAltitude => meta.gps_altitude = first_rational(v),
+            ExifTag::GPSLatitudeRef => lat_ref = ascii(v),
+            ExifTag::GPSLongitudeRef => lon_ref = ascii(v),
+            ExifTag::GPSAltitudeRef => alt_ref = first_integer(v).or_else(|| match v {
+                TagValue::U8(b) => b.first().map(|&b| b as u32),
+                _ => None,
+            }),
+            ExifTag::UnknownToMe if entry.ifd.tag == TAG_BODY_SERIAL_NUMBER => meta.serial_number = ascii(v),
+            _ => {}
         }
     }
-    None
+    if lat_ref.as_deref() == Some("S") {
+        meta.gps_latitude = meta.gps_latitude.map(|l| -l);
+    }
+    if lon_ref.as_deref() == Some("W") {
+        meta.gps_longitude = meta.gps_longitude.map(|l| -l);
+    }
+    if alt_ref == Some(1) {
+        meta.gps_altitude = meta.gps_altitude.map(|a| -a);
+    }
+    meta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little-endian TIFF tag: (tag, type, count, value bytes).
+    type Tag = (u16, u16, u32, Vec<u8>);
+
+    fn rational(values: &[(u32, u32)]) -> Vec<u8> {
+        values.iter().flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes())).collect()
+    }
+
+    fn ifd_len(tags: &[Tag]) -> u32 {
+        6 + 12 * tags.len() as u32 + tags.iter().map(|t| if t.3.len() > 4 { t.3.len() as u32 } else { 0 }).sum::<u32>()
+    }
+
+    fn write_ifd(out: &mut Vec<u8>, tags: &[Tag]) {
+        let mut data_at = out.len() as u32 + 6 + 12 * tags.len() as u32;
+        let mut data: Vec<u8> = Vec::new();
+        out.extend((tags.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in tags {
+            out.extend(tag.to_le_bytes());
+            out.extend(kind.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            if value.len() > 4 {
+                out.extend(data_at.to_le_bytes());
+                data_at += value.len() as u32;
+                data.extend(value);
+            } else {
+                out.extend(value.iter().copied().chain(std::iter::repeat(0)).take(4));
+            }
+        }
+        out.extend(0u32.to_le_bytes());
+        out.extend(data);
+    }
+
+    /// A TIFF with IFD0, Exif and GPS IFDs as a camera would write them.
+    fn exif_tiff(lat_ref: &str, lon_ref: &str, below_sea_level: bool) -> Vec<u8> {
+        let exif: Vec<Tag> = vec![
+            (0x829a, 5, 1, rational(&[(1, 250)])),
+            (0x829d, 5, 1, rational(&[(28, 10)])),
+            (0x8827, 3, 1, 800u16.to_le_bytes().to_vec()),
+            (0x920a, 5, 1, rational(&[(50, 1)])),
+            (0xa431, 2, 6, b"12345\0".to_vec()),
+        ];
+        let gps: Vec<Tag> = vec![
+            (0x0001, 2, 2, format!("{}\0", lat_ref).into_bytes()),
+            (0x0002, 5, 3, rational(&[(33, 1), (30, 1), (0, 1)])),
+            (0x0003, 2, 2, format!("{}\0", lon_ref).into_bytes()),
+            (0x0004, 5, 3, rational(&[(70, 1), (15, 1), (0, 1)])),
+            (0x0005, 1, 1, vec![below_sea_level as u8]),
+            (0x0006, 5, 1, rational(&[(12, 1)])),
+        ];
+        let mut ifd0: Vec<Tag> = vec![
+            (0x010f, 2, 6, b"Canon\0".to_vec()),
+            (0x0110, 2, 7, b"EOS\tR5\0".to_vec()),
+            (0x8769, 4, 1, vec![0; 4]),
+            (0x8825, 4, 1, vec![0; 4]),
+        ];
+        let exif_at = 8 + ifd_len(&ifd0);
+        let gps_at = exif_at + ifd_len(&exif);
+        ifd0[2].3 = exif_at.to_le_bytes().to_vec();
+        ifd0[3].3 = gps_at.to_le_bytes().to_vec();
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        for tags in [&ifd0, &exif, &gps] {
+            write_ifd(&mut out, tags);
+        }
+        out
+    }
+
+    #[test]
+    fn reads_typed_fields_and_gps_signs() {
+        let south_west = extract_metadata_from_buffer(&exif_tiff("S", "W", true));
+        assert_eq!(south_west.exposure_time, Some(0.004));
+        assert_eq!((south_west.aperture, south_west.iso, south_west.focal_length), (Some(2.8), Some(800), Some(50.0)));
+        assert_eq!(south_west.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(south_west.serial_number.as_deref(), Some("12345"));
+        assert_eq!((south_west.gps_latitude, south_west.gps_longitude, south_west.gps_altitude), (Some(-33.5), Some(-70.25), Some(-12.0)));
+
+        let north_east = extract_metadata_from_buffer(&exif_tiff("N", "E", false));
+        assert_eq!((north_east.gps_latitude, north_east.gps_longitude, north_east.gps_altitude), (Some(33.5), Some(70.25), Some(12.0)));
+
+        // Control characters never reach tab-separated output
+        let fields = south_west.fields();
+        assert_eq!(fields[0], ("exposure_time", "0.004".to_string()));
+        assert!(fields.contains(&("camera", "EOS R5".to_string())));
+        assert!(!fields.iter().any(|(key, _)| *key == "lens"));
+    }
+
+    #[test]
+    fn files_without_exif_are_empty() {
+        let path = std::env::temp_dir().join(format!("grepfuzz-metadata-{}.tif", std::process::id()));
+        std::fs::write(&path, exif_tiff("S", "W", false)).unwrap();
+        assert_eq!(extract_metadata(&path).gps_latitude, Some(-33.5));
+        std::fs::write(&path, b"not an image").unwrap();
+        assert_eq!(extract_metadata(&path), ImageMetadata::default());
+        assert!(ImageMetadata::default().fields().is_empty());
+        std::fs::remove_file(&path).ok();
+    }
 }
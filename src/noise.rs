@@ -0,0 +1,162 @@
+use image::{ImageBuffer, Luma};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use crate::blur_detector::BlurDetector;
+use crate::detector_helpers::detector_threshold;
+
+/// Side length of the synthetic noise patch used to measure each detector's noise floor.
+const NOISE_PATCH_SIZE: u32 = 128;
+
+/// Noise floors are measured at multiples of this sigma, so nearby estimates share one measurement.
+const SIGMA_STEP: f64 = 0.1;
+
+/// Floors already measured, by detector name and sigma step.
+static FLOORS: LazyLock<Mutex<HashMap<(&'static str, i64), f64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Noise estimate for one image and what it does to each detector.
+#[derive(Debug, Clone)]
+pub struct NoiseReport {
+    /// Sigma measured from the wavelet HH band, in 8-bit gray levels.
+    pub measured_sigma: f64,
+    /// ISO from EXIF, if known.
+    pub iso: Option<u32>,
+    /// Final estimate after applying the ISO prior (equal to `measured_sigma` without one).
+    pub sigma: f64,
+    /// Score each detector gives pure noise of `sigma`; subtracted from scores when compensating.
+    pub floors: Vec<(String, f64)>,
+    pub compensated: bool,
+}
+
+/// Estimates the noise standard deviation from the finest diagonal (HH) Haar wavelet band.
+///
+/// Uses the robust median-absolute-deviation estimator `sigma = median(|HH|) / 0.6745`,
+/// which ignores the sparse large coefficients produced by real edges.
+pub fn estimate_noise_sigma(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> f64 {
+    let (w, h) = (img.width() / 2, img.height() / 2);
+    let mut coeffs = Vec::with_capacity((w * h) as usize);
+    for by in 0..h {
+        for bx in 0..w {
+            let (x, y) = (bx * 2, by * 2);
+            let a = img.get_pixel(x, y)[0] as f64;
+            let b = img.get_pixel(x + 1, y)[0] as f64;
+            let c = img.get_pixel(x, y + 1)[0] as f64;
+            let d = img.get_pixel(x + 1, y + 1)[0] as f64;
+            coeffs.push(((a - b - c + d) / 2.0).abs());
+        }
+    }
+    if coeffs.is_empty() {
+        return 0.0;
+    }
+    let mid = coeffs.len() / 2;
+    let (_, median, _) = coeffs.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    *median / 0.6745
+}
+
+/// Expected sensor noise for an ISO, scaled from `base_sigma` at ISO 100 (shot noise grows with sqrt(gain)).
+pub fn iso_prior_sigma(iso: u32, base_sigma: f64) -> f64 {
+    base_sigma * (iso.max(1) as f64 / 100.0).sqrt()
+}
+
+/// Combines the measured sigma with the ISO prior as a geometric mean, so texture mistaken for
+/// noise at low ISO and clean-looking high-ISO frames are both pulled toward the prior.
+pub fn combine_with_prior(measured: f64, prior: f64) -> f64 {
+    (measured.max(0.0) * prior.max(0.0)).sqrt()
+}
+
+/// Builds a mid-gray patch of Gaussian noise with standard deviation `sigma`.
+pub fn noise_patch(sigma: f64, mean: f64) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    // Fixed seed so that floors, and therefore verdicts, are reproducible between runs
+    let mut rng = StdRng::seed_from_u64(0x006e_6f69_7365);
+    ImageBuffer::from_fn(NOISE_PATCH_SIZE, NOISE_PATCH_SIZE, |_, _| {
+        // Box-Muller transform
+        let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+        let u2: f64 = rng.gen();
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        Luma([(mean + sigma * z).round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+/// Measures the score `det` gives to pure noise of the given sigma, rounded to a multiple of
+/// [`SIGMA_STEP`]. The floor depends on nothing else, so each is measured once per process.
+pub fn noise_floor(det: &dyn BlurDetector, sigma: f64) -> f64 {
+    if sigma <= 0.0 {
+        return 0.0;
+    }
+    let key = (det.name(), (sigma / SIGMA_STEP).round() as i64);
+    if let Some(&floor) = FLOORS.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+        return floor;
+    }
+    let floor = det.detect(&noise_patch(key.1 as f64 * SIGMA_STEP, 128.0)).0;
+    FLOORS.lock().unwrap_or_else(PoisonError::into_inner).insert(key, floor);
+    floor
+}
+
+/// Wraps a detector and subtracts its noise floor from every score before thresholding.
+pub struct NoiseCompensatedDetector<'a> {
+    pub inner: &'a dyn BlurDetector,
+    pub floor: f64,
+}
+
+impl BlurDetector for NoiseCompensatedDetector<'_> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn detect(&self, img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> (f64, bool) {
+        let (raw, _) = self.inner.detect(img);
+        let value = (raw - self.floor).max(0.0);
+        (value, value < detector_threshold(self.inner))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        // Expose the wrapped detector so threshold lookups keep working
+        self.inner.as_any()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_recovers_synthetic_noise() {
+        let patch = noise_patch(8.0, 128.0);
+        let sigma = estimate_noise_sigma(&patch);
+        assert!((sigma - 8.0).abs() < 1.0, "Expected sigma near 8.0, got {}", sigma);
+    }
+
+    #[test]
+    fn test_flat_image_has_no_noise() {
+        let flat: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_pixel(64, 64, Luma([90]));
+        assert_eq!(estimate_noise_sigma(&flat), 0.0);
+    }
+
+    struct CountingDetector(std::cell::Cell<usize>);
+
+    impl BlurDetector for CountingDetector {
+        fn detect(&self, img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> (f64, bool) {
+            self.0.set(self.0.get() + 1);
+            (estimate_noise_sigma(img), false)
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        fn name(&self) -> &'static str {
+            "CountingNoise"
+        }
+    }
+
+    #[test]
+    fn floors_are_measured_once_per_sigma_step() {
+        let det = CountingDetector(std::cell::Cell::new(0));
+        let floor = noise_floor(&det, 4.01);
+        assert!((floor - 4.0).abs() < 0.5, "Expected a floor near 4.0, got {}", floor);
+        assert_eq!(noise_floor(&det, 3.98), floor);
+        assert_eq!(det.0.get(), 1);
+        noise_floor(&det, 6.0);
+        assert_eq!(det.0.get(), 2);
+    }
+}
@@ -1,28 +1,44 @@
-use crate::blur_result::BlurResult;
+use crate::blur_result::ImageReport;
+use crate::config::GrepfuzzConfig;
 use std::io::{self, Write};
 use ansi_term::Colour::{Green, Red};
 
 pub fn print_results<W: Write>(
     writer: &mut W,
-    is_blurry: bool,
-    results: &[BlurResult],
-    size: u64,
-    width: u32,
-    height: u32,
-    focal: &Option<String>,
+    report: &ImageReport,
     filename: &str,
     verbose: bool,
     ascii: bool,
+    config: &GrepfuzzConfig,
 ) -> io::Result<()> {
+    let focal = &report.focal;
     if ascii {
-        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}", filename, is_blurry, size, width, height, focal.as_deref().unwrap_or("-"),
-            results.iter().map(|r| format!("{}:{}:{}", r.name, r.value, r.is_blurry)).collect::<Vec<_>>().join(","))?;
+        let mut fields: Vec<String> = report.results.iter().map(|r| format!("{}:{}:{}", r.name, r.value, r.is_blurry)).collect();
+        fields.extend(report.tiles.iter().map(|t| format!("{}@tiles:{}:{}", t.name, t.top_k_mean, t.is_blurry)));
+        for roi in &report.rois {
+            fields.extend(roi.results.iter().map(|r| format!("{}@roi[{}]:{}:{}", r.name, roi_geometry(roi), r.value, r.is_blurry)));
+        }
+        for frame in &report.frames {
+            fields.extend(frame.results.iter().map(|r| format!("{}@frame[{}]:{}:{}", r.name, frame_label(frame), r.value, r.is_blurry)));
+        }
+        write!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}", filename, report.is_blurry, report.size, report.width, report.height, focal.as_deref().unwrap_or("-"),
+            fields.join(","))?;
+        writeln!(writer, "{}", extra_columns(report, config))?;
     } else if verbose {
         writeln!(writer, "File: {}", filename)?;
-        writeln!(writer, "  Size: {} bytes", size)?;
-        writeln!(writer, "  Dimensions: {}x{}", width, height)?;
+        writeln!(writer, "  Size: {} bytes", report.size)?;
+        writeln!(writer, "  Dimensions: {}x{}", report.width, report.height)?;
+        if let Some(p) = report.preview {
+            writeln!(writer, "  RAW preview: {}x{} embedded JPEG ({} bytes)", p.width, p.height, p.length)?;
+        }
+        if report.orientation != 1 {
+            writeln!(writer, "  Orientation: EXIF {} applied (dimensions and coordinates are upright)", report.orientation)?;
+        }
         writeln!(writer, "  Focal Length: {}", focal.as_deref().unwrap_or("-"))?;
-        for r in results {
+        for (key, value) in report.metadata.fields() {
+            writeln!(writer, "  EXIF {}: {}", key, value)?;
+        }
+        for r in &report.results {
             let blur_str = if r.is_blurry {
                 Red.paint("BLURRY")
             } else {
@@ -30,14 +46,162 @@ pub fn print_results<W: Write>(
             };
             writeln!(writer, "  {}: value = {:.3}, blurry = {} (threshold: {:.3})", r.name, r.value, blur_str, r.threshold)?;
         }
-        let overall_str = if is_blurry {
+        for t in &report.tiles {
+            let blur_str = if t.is_blurry {
+                Red.paint("BLURRY")
+            } else {
+                Green.paint("SHARP")
+            };
+            writeln!(writer, "  {} tiles ({}x{}): max = {:.3}, top-{} mean = {:.3}, sharp fraction = {:.2}, blurry = {}",
+                t.name, t.grid, t.grid, t.max_value, t.top_k, t.top_k_mean, t.sharp_fraction, blur_str)?;
+        }
+        for roi in &report.rois {
+            let (x, y, w, h) = roi.rect;
+            writeln!(writer, "  ROI {} ({}x{} at {},{}): blurry = {}", roi.roi, w, h, x, y,
+                if roi.is_blurry { Red.paint("BLURRY") } else { Green.paint("SHARP") })?;
+            for r in &roi.results {
+                let blur_str = if r.is_blurry {
+                    Red.paint("BLURRY")
+                } else {
+                    Green.paint("SHARP")
+                };
+                writeln!(writer, "    {}: value = {:.3}, blurry = {} (threshold: {:.3})", r.name, r.value, blur_str, r.threshold)?;
+            }
+        }
+        for frame in &report.frames {
+            let at = frame.timestamp.map(|t| format!(" at {:.3}s", t)).unwrap_or_default();
+            writeln!(writer, "  Frame {}{}: blurry = {}", frame.index, at,
+                if frame.is_blurry { Red.paint("BLURRY") } else { Green.paint("SHARP") })?;
+            if let Some(ref export) = frame.export {
+                writeln!(writer, "    Exported: {}", export.display())?;
+            }
+            for r in &frame.results {
+                let blur_str = if r.is_blurry {
+                    Red.paint("BLURRY")
+                } else {
+                    Green.paint("SHARP")
+                };
+                writeln!(writer, "    {}: value = {:.3}, blurry = {} (threshold: {:.3})", r.name, r.value, blur_str, r.threshold)?;
+            }
+        }
+        if let Some(ref noise) = report.noise {
+            let iso = noise.iso.map(|i| i.to_string()).unwrap_or_else(|| "-".to_string());
+            writeln!(writer, "  Noise: sigma = {:.3} (measured {:.3}, ISO {})", noise.sigma, noise.measured_sigma, iso)?;
+            if noise.compensated {
+                for (name, floor) in &noise.floors {
+                    writeln!(writer, "    {} noise floor = {:.3} (subtracted)", name, floor)?;
+                }
+            }
+        }
+        if let Some(ref exposure) = report.exposure {
+            writeln!(writer, "  Exposure: mean luminance = {:.1}, clipped highlights = {:.2}%, crushed shadows = {:.2}%",
+                exposure.mean_luminance, exposure.clipped_highlights * 100.0, exposure.crushed_shadows * 100.0)?;
+        }
+        if let Some(ref burst) = report.burst {
+            writeln!(writer, "  Burst {}: rank {} of {}, score = {:.3}, {}", burst.id, burst.rank, burst.size, burst.score,
+                if burst.keep { Green.paint("KEEP") } else { Red.paint("REJECT") })?;
+        }
+        if let Some(ref dup) = report.duplicate {
+            writeln!(writer, "  Duplicate cluster {}: {} images, distance to keeper = {}, {}", dup.cluster, dup.size, dup.distance,
+                if dup.keeper { Green.paint("KEEP") } else { Red.paint("REJECT") })?;
+        }
+        if let Some(ref rel) = report.relative {
+            writeln!(writer, "  Relative group {}: rank {} of {} (softest first), {:+.2} std from median, {}", rel.group, rel.rank, rel.size, rel.deviation,
+                if rel.flagged { Red.paint("FLAGGED") } else { Green.paint("KEEP") })?;
+        }
+        if let Some(p) = report.blur_probability {
+            writeln!(writer, "  Combiner: blur probability = {:.3}", p)?;
+        }
+        if let Some(ref heatmap) = report.heatmap {
+            writeln!(writer, "  Heatmap: {}", heatmap.display())?;
+        }
+        let overall_str = if report.is_blurry {
             Red.paint("BLURRY")
         } else {
             Green.paint("SHARP")
         };
         writeln!(writer, "  Overall blurry: {}", overall_str)?;
     } else {
-        writeln!(writer, "{}\t{}", filename, is_blurry)?;
+        writeln!(writer, "{}\t{}", filename, report.is_blurry)?;
+    }
+    Ok(())
+}
+
+/// Prints one tab-separated row per detector (and per tiled detector), as used by the stdin `--ascii` mode.
+pub fn print_ascii_rows<W: Write>(writer: &mut W, report: &ImageReport, path: &str, config: &GrepfuzzConfig) -> io::Result<()> {
+    let extra = extra_columns(report, config);
+    for res in &report.results {
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.3}\t{}{}", path, report.size, report.width, report.height, res.name, res.value, res.threshold, if res.is_blurry { "BLURRY" } else { "SHARP" }, extra)?;
+    }
+    for (t, res) in report.tiles.iter().zip(&report.results) {
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}@tiles\t{:.6}\t{:.3}\t{}{}", path, report.size, report.width, report.height, t.name, t.top_k_mean, res.threshold, if t.is_blurry { "BLURRY" } else { "SHARP" }, extra)?;
+    }
+    for roi in &report.rois {
+        for res in &roi.results {
+            writeln!(writer, "{}\t{}\t{}\t{}\t{}@roi[{}]\t{:.6}\t{:.3}\t{}{}", path, report.size, report.width, report.height, res.name, roi_geometry(roi), res.value, res.threshold, if res.is_blurry { "BLURRY" } else { "SHARP" }, extra)?;
+        }
+    }
+    for frame in &report.frames {
+        for res in &frame.results {
+            writeln!(writer, "{}\t{}\t{}\t{}\t{}@frame[{}]\t{:.6}\t{:.3}\t{}{}", path, report.size, report.width, report.height, res.name, frame_label(frame), res.value, res.threshold, if res.is_blurry { "BLURRY" } else { "SHARP" }, extra)?;
+        }
     }
     Ok(())
 }
+
+/// Formats a resolved region as `WxH+X+Y`, free of the `,`/`:` separators used by `--ascii`.
+fn roi_geometry(roi: &crate::roi::RoiReport) -> String {
+    let (x, y, w, h) = roi.rect;
+    format!("{}x{}+{}+{}", w, h, x, y)
+}
+
+/// Frame index, with the timestamp for video frames: `120` or `120@4.000s`.
+fn frame_label(frame: &crate::frames::FrameReport) -> String {
+    match frame.timestamp {
+        Some(t) => format!("{}@{:.3}s", frame.index, t),
+        None => frame.index.to_string(),
+    }
+}
+
+/// Optional trailing `\tkey=value` columns for `--ascii` output; empty when no extras are enabled,
+/// so the default column layout never changes.
+fn extra_columns(report: &ImageReport, config: &GrepfuzzConfig) -> String {
+    let mut extra = String::new();
+    if let Some(p) = report.preview {
+        extra.push_str(&format!("\tpreview={}x{}", p.width, p.height));
+    }
+    if let Some(ref burst) = report.burst {
+        extra.push_str(&format!("\tburst={}\tburst_size={}\tburst_rank={}\tburst_score={:.3}\tburst_keep={}",
+            burst.id, burst.size, burst.rank, burst.score, burst.keep));
+    }
+    if let Some(hash) = report.hash {
+        extra.push_str(&format!("\thash={:016x}", hash));
+    }
+    if let Some(ref dup) = report.duplicate {
+        extra.push_str(&format!("\tdup_cluster={}\tdup_size={}\tdup_distance={}\tdup_keeper={}",
+            dup.cluster, dup.size, dup.distance, dup.keeper));
+    }
+    if let Some(ref rel) = report.relative {
+        extra.push_str(&format!("\trel_group={}\trel_size={}\trel_rank={}\trel_deviation={:.3}\trel_flagged={}",
+            rel.group, rel.size, rel.rank, rel.deviation, rel.flagged));
+    }
+    if let Some(p) = report.blur_probability {
+        extra.push_str(&format!("\tp_blurry={:.4}", p));
+    }
+    if !report.frames.is_empty() {
+        extra.push_str(&format!("\tframes={}", report.frames.len()));
+    }
+    if let Some(ref noise) = report.noise {
+        extra.push_str(&format!("\tnoise={:.3}", noise.sigma));
+    }
+    if let Some(ref exposure) = report.exposure {
+        extra.push_str(&format!("\tmean_luminance={:.1}\tclipped_highlights={:.4}\tcrushed_shadows={:.4}",
+            exposure.mean_luminance, exposure.clipped_highlights, exposure.crushed_shadows));
+    }
+    if config.metadata.report.unwrap_or(false) {
+        for (key, value) in report.metadata.fields() {
+            extra.push_str(&format!("\t{}={}", key, value));
+        }
+    }
+    extra
+}
@@ -0,0 +1,278 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Camera RAW extensions whose embedded JPEG preview is analyzed instead of the raw sensor data.
+pub const RAW_EXTENSIONS: [&str; 13] = [
+    "cr2", "cr3", "crw", "nef", "nrw", "arw", "srf", "sr2", "raf", "dng", "orf", "rw2", "pef",
+];
+
+/// Location and size of a JPEG preview embedded in a RAW container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawPreview {
+    pub offset: usize,
+    pub length: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RawPreview {
+    pub fn bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.offset..self.offset + self.length]
+    }
+
+    /// Reads just the preview's bytes from the RAW file at `path`.
+    pub fn read_from(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut file = std::fs::File::open(path)?;
+        file.seek(SeekFrom::Start(self.offset as u64))?;
+        let mut bytes = vec![0; self.length];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+pub fn is_raw_path(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| RAW_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Finds the largest (by pixel count) JPEG preview embedded in a RAW file.
+///
+/// TIFF-based containers (CR2, NEF, ARW, DNG, ORF, PEF, ...) are walked through IFD0, its
+/// chained IFDs and SubIFDs; Fuji RAF uses the offset in its header. ISO-BMFF (CR3) and any
+/// previews the structured walk missed are found by scanning for complete JPEG streams.
+pub fn find_largest_preview(data: &[u8]) -> Option<RawPreview> {
+    let mut candidates = Vec::new();
+    if data.starts_with(b"FUJIFILMCCD-RAW") && data.len() >= 92 {
+        let offset = read_u32(data, 84, false)? as usize;
+        let length = read_u32(data, 88, false)? as usize;
+        candidates.push((offset, length));
+        // The embedded JPEG in a RAF is a plain EXIF JPEG; its TIFF block is not walked
+    } else if let Some(le) = tiff_byte_order(data) {
+        tiff_candidates(data, le, &mut candidates);
+    }
+    let mut previews: Vec<RawPreview> = candidates.into_iter()
+        .filter_map(|(offset, length)| jpeg_preview(data, offset, Some(length)))
+        .collect();
+    previews.extend(scan_jpegs(data));
+    previews.into_iter().max_by_key(|p| (p.width as u64 * p.height as u64, p.length))
+}
+
+/// EXIF Orientation (1-8) from IFD0 of a TIFF-based RAW. Embedded previews usually lack their own.
+pub fn raw_orientation(data: &[u8]) -> Option<u8> {
+    let le = tiff_byte_order(data)?;
+    let ifd = read_u32(data, 4, le)? as usize;
+    let count = read_u16(data, ifd, le)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read_u16(data, entry, le) == Some(0x0112))
+        .and_then(|entry| read_u16(data, entry + 8, le))
+        .filter(|o| (1..=8).contains(o))
+        .map(|o| o as u8)
+}
+
+fn read_u16(data: &[u8], pos: usize, le: bool) -> Option<u16> {
+    let b: [u8; 2] = data.get(pos..pos + 2)?.try_into().ok()?;
+    Some(if le { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+}
+
+fn read_u32(data: &[u8], pos: usize, le: bool) -> Option<u32> {
+    let b: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+    Some(if le { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+}
+
+/// Byte order of a TIFF-style header; includes the Olympus (`IIRO`/`MMOR`) and Panasonic (`IIU\0`) variants.
+fn tiff_byte_order(data: &[u8]) -> Option<bool> {
+    match data.get(0..4)? {
+        [b'I', b'I', 0x2a, 0] | [b'I', b'I', b'R', b'O'] | [b'I', b'I', b'U', 0] => Some(true),
+        [b'M', b'M', 0, 0x2a] | [b'M', b'M', b'O', b'R'] => Some(false),
+        _ => None,
+    }
+}
+
+/// Collects (offset, length) of JPEG previews referenced from the TIFF IFD tree.
+fn tiff_candidates(data: &[u8], le: bool, out: &mut Vec<(usize, usize)>) {
+    const MAX_IFDS: usize = 64;
+    let mut queue = vec![read_u32(data, 4, le).unwrap_or(0) as usize];
+    let mut seen = Vec::new();
+    while let Some(ifd) = queue.pop() {
+        if ifd == 0 || ifd >= data.len() || seen.contains(&ifd) || seen.len() >= MAX_IFDS {
+            continue;
+        }
+        seen.push(ifd);
+        let count = match read_u16(data, ifd, le) {
+            Some(c) => c as usize,
+            None => continue,
+        };
+        let (mut jpeg_offset, mut jpeg_length) = (None, None);
+        let (mut strip_offset, mut strip_length, mut compression) = (None, None, None);
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            let (Some(tag), Some(kind), Some(n)) = (read_u16(data, entry, le), read_u16(data, entry + 2, le), read_u32(data, entry + 4, le)) else {
+                break;
+            };
+            // Single SHORT/LONG values live inline in the entry
+            let value = if kind == 3 { read_u16(data, entry + 8, le).map(|v| v as u32) } else { read_u32(data, entry + 8, le) };
+            match tag {
+                0x0103 => compression = value,
+                0x0111 if n == 1 => strip_offset = value,
+                0x0117 if n == 1 => strip_length = value,
+                0x0201 => jpeg_offset = value,
+                0x0202 => jpeg_length = value,
+                // SubIFDs: one inline offset, or a pointer to an array of offsets
+                0x014a => {
+                    if n == 1 {
+                        queue.extend(value.map(|v| v as usize));
+                    } else if let Some(ptr) = value {
+                        for k in 0..n.min(16) as usize {
+                            queue.extend(read_u32(data, ptr as usize + k * 4, le).map(|v| v as usize));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let (Some(o), Some(l)) = (jpeg_offset, jpeg_length) {
+            out.push((o as usize, l as usize));
+        }
+        // Compression 6 (old JPEG) and 7 (JPEG) strips are usually full-size previews
+        if let (Some(o), Some(l), Some(6 | 7)) = (strip_offset, strip_length, compression) {
+            out.push((o as usize, l as usize));
+        }
+        queue.extend(read_u32(data, ifd + 2 + count * 12, le).map(|v| v as usize));
+    }
+}
+
+/// Validates a JPEG stream at `offset` and reads its dimensions from the SOF marker.
+/// When `length` is unknown, the stream is followed to its EOI marker.
+fn jpeg_preview(data: &[u8], offset: usize, length: Option<usize>) -> Option<RawPreview> {
+    if data.get(offset..offset + 3)? != [0xff, 0xd8, 0xff] {
+        return None;
+    }
+    let mut pos = offset + 2;
+    let (mut width, mut height) = (0, 0);
+    loop {
+        // Skip fill bytes between segments
+        while data.get(pos)? == &0xff && data.get(pos + 1)? == &0xff {
+            pos += 1;
+        }
+        if data.get(pos)? != &0xff {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        let seg_len = read_u16(data, pos + 2, false)? as usize;
+        if matches!(marker, 0xc0..=0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf) {
+            height = read_u16(data, pos + 5, false)? as u32;
+            width = read_u16(data, pos + 7, false)? as u32;
+        }
+        pos += 2 + seg_len;
+        if marker == 0xda {
+            break;
+        }
+    }
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let length = match length {
+        Some(l) if offset + l <= data.len() => l,
+        Some(_) => return None,
+        None => {
+            // Entropy-coded data never contains FFD9 (stuffed FF00 and restart markers aside),
+            // so the first one ends the stream, across all scans of a progressive JPEG
+            let end = pos + data.get(pos..)?.windows(2).position(|w| w == [0xff, 0xd9])?;
+            end + 2 - offset
+        }
+    };
+    Some(RawPreview { offset, length, width, height })
+}
+
+/// Finds every complete JPEG stream in `data` (used for ISO-BMFF containers such as CR3).
+fn scan_jpegs(data: &[u8]) -> Vec<RawPreview> {
+    let mut previews = Vec::new();
+    let mut pos = 0;
+    while pos + 3 <= data.len() {
+        match data[pos..].windows(3).position(|w| w == [0xff, 0xd8, 0xff]) {
+            Some(i) => {
+                let start = pos + i;
+                match jpeg_preview(data, start, None) {
+                    Some(p) => {
+                        pos = p.offset + p.length;
+                        previews.push(p);
+                    }
+                    None => pos = start + 2,
+                }
+            }
+            None => break,
+        }
+    }
+    previews
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| Luma([((x * 7 + y * 3) % 256) as u8]));
+        let mut out = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut out).encode_image(&img).unwrap();
+        out
+    }
+
+    #[test]
+    fn picks_largest_tiff_preview() {
+        let (thumb, full) = (jpeg(16, 12), jpeg(64, 48));
+        // Little-endian TIFF: IFD0 (thumbnail, orientation 6) chained to IFD1 (full-size preview)
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        let ifd1 = 8 + 2 + 3 * 12 + 4;
+        let thumb_at = ifd1 + 2 + 2 * 12 + 4;
+        let full_at = thumb_at + thumb.len();
+        let entry = |data: &mut Vec<u8>, tag: u16, kind: u16, value: u32| {
+            data.extend(tag.to_le_bytes());
+            data.extend(kind.to_le_bytes());
+            data.extend(1u32.to_le_bytes());
+            data.extend(value.to_le_bytes());
+        };
+        data.extend(3u16.to_le_bytes());
+        entry(&mut data, 0x0112, 3, 6);
+        entry(&mut data, 0x0201, 4, thumb_at as u32);
+        entry(&mut data, 0x0202, 4, thumb.len() as u32);
+        data.extend((ifd1 as u32).to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        entry(&mut data, 0x0201, 4, full_at as u32);
+        entry(&mut data, 0x0202, 4, full.len() as u32);
+        data.extend(0u32.to_le_bytes());
+        data.extend(&thumb);
+        data.extend(&full);
+
+        let preview = find_largest_preview(&data).unwrap();
+        assert_eq!((preview.width, preview.height), (64, 48));
+        assert_eq!(preview.bytes(&data), &full[..]);
+        assert_eq!(raw_orientation(&data), Some(6));
+    }
+
+    #[test]
+    fn finds_raf_and_scanned_previews() {
+        let preview = jpeg(32, 24);
+        let mut raf = b"FUJIFILMCCD-RAW 0201".to_vec();
+        raf.resize(100, 0);
+        raf[84..88].copy_from_slice(&100u32.to_be_bytes());
+        raf[88..92].copy_from_slice(&(preview.len() as u32).to_be_bytes());
+        raf.extend(&preview);
+        let found = find_largest_preview(&raf).unwrap();
+        assert_eq!((found.offset, found.length, found.width), (100, preview.len(), 32));
+        let path = std::env::temp_dir().join(format!("grepfuzz-preview-{}.raf", std::process::id()));
+        std::fs::write(&path, &raf).unwrap();
+        assert_eq!(found.read_from(&path).unwrap(), preview);
+        std::fs::remove_file(&path).ok();
+
+        // Unknown container (e.g. CR3): previews are found by scanning for JPEG streams
+        let mut boxed = b"\0\0\0\x18ftypcrx ".to_vec();
+        boxed.extend(&preview);
+        boxed.extend(b"trailing box data");
+        let found = find_largest_preview(&boxed).unwrap();
+        assert_eq!(found.bytes(&boxed), &preview[..]);
+    }
+}
@@ -0,0 +1,115 @@
+use serde::Deserialize;
+use std::path::Path;
+use crate::blur_result::ImageReport;
+use crate::burst::{assign_bursts, combined_score};
+
+/// Which images are compared with each other in relative mode.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelativeGroup {
+    /// The whole input is one set
+    All,
+    /// Images in the same directory
+    Directory,
+    /// Images of the same burst (capture time and camera)
+    Burst,
+}
+
+/// Where an image stands within its group in relative mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeMembership {
+    /// Zero-based group number, in order of each group's first appearance in the input.
+    pub group: usize,
+    /// Number of images in the group.
+    pub size: usize,
+    /// 1 for the softest image of the group.
+    pub rank: usize,
+    /// Score the ranking used: the combined score, or one minus the combiner's blur probability.
+    pub score: f64,
+    /// Standard deviations from the group median; negative is softer than typical.
+    pub deviation: f64,
+    /// True if the image is among the worst of its group.
+    pub flagged: bool,
+}
+
+/// Sharpness score for comparing images of one batch; higher is sharper.
+pub fn relative_score(report: &ImageReport) -> f64 {
+    match report.blur_probability {
+        Some(p) => 1.0 - p,
+        None => combined_score(&report.results),
+    }
+}
+
+/// Group number of each image, in order of first appearance. Burst grouping uses the same rules as
+/// burst mode, with bursts `gap_seconds` apart.
+pub fn group_ids(paths: &[&str], reports: &[&ImageReport], group: RelativeGroup, gap_seconds: f64) -> Vec<usize> {
+    match group {
+        RelativeGroup::All => vec![0; reports.len()],
+        RelativeGroup::Burst => assign_bursts(reports, gap_seconds, 1).into_iter().map(|b| b.id).collect(),
+        RelativeGroup::Directory => {
+            let mut dirs: Vec<&Path> = Vec::new();
+            paths.iter()
+                .map(|p| {
+                    let dir = Path::new(p).parent().unwrap_or(Path::new(""));
+                    dirs.iter().position(|d| *d == dir).unwrap_or_else(|| {
+                        dirs.push(dir);
+                        dirs.len() - 1
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// Ranks each group by score and flags the images in its bottom `bottom_percent` (rounded down, so
+/// a lone image is never flagged by it) or more than `below_median_std` standard deviations below
+/// its median. Returns one membership per score, in input order.
+pub fn assign_relative(scores: &[f64], groups: &[usize], bottom_percent: Option<f64>, below_median_std: Option<f64>) -> Vec<RelativeMembership> {
+    let mut memberships = vec![None; scores.len()];
+    let count = groups.iter().max().map_or(0, |g| g + 1);
+    for id in 0..count {
+        let mut members: Vec<usize> = (0..scores.len()).filter(|&i| groups[i] == id).collect();
+        members.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]).then(a.cmp(&b)));
+        let size = members.len();
+        let median = if size % 2 == 1 {
+            scores[members[size / 2]]
+        } else {
+            (scores[members[size / 2 - 1]] + scores[members[size / 2]]) / 2.0
+        };
+        let mean = members.iter().map(|&i| scores[i]).sum::<f64>() / size as f64;
+        let std = (members.iter().map(|&i| (scores[i] - mean).powi(2)).sum::<f64>() / size as f64).sqrt();
+        let bottom = bottom_percent.map_or(0, |p| (size as f64 * p / 100.0).floor() as usize);
+        for (rank, i) in members.into_iter().enumerate() {
+            let deviation = if std > 0.0 { (scores[i] - median) / std } else { 0.0 };
+            let flagged = rank < bottom || below_median_std.is_some_and(|k| deviation < -k);
+            memberships[i] = Some(RelativeMembership { group: id, size, rank: rank + 1, score: scores[i], deviation, flagged });
+        }
+    }
+    memberships.into_iter().map(|m| m.expect("every image is in a group")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_the_worst_of_each_group() {
+        let scores = [0.5, 0.9, 1.0, 0.2, 1.1, 0.95, 3.0];
+        let groups = [0, 0, 0, 0, 0, 0, 1];
+        let rel = assign_relative(&scores, &groups, Some(20.0), None);
+        // 20% of 6 rounds down to the single softest image; the lone image is never flagged
+        assert_eq!(rel.iter().map(|r| r.flagged).collect::<Vec<_>>(), vec![false, false, false, true, false, false, false]);
+        assert_eq!(rel.iter().map(|r| r.rank).collect::<Vec<_>>(), vec![2, 3, 5, 1, 6, 4, 1]);
+        let rel = assign_relative(&scores, &groups, None, Some(1.0));
+        assert_eq!(rel.iter().map(|r| r.flagged).collect::<Vec<_>>(), vec![true, false, false, true, false, false, false]);
+        assert!(rel[3].deviation < rel[0].deviation && rel[6].deviation == 0.0);
+    }
+
+    #[test]
+    fn groups_by_directory() {
+        let reports = [ImageReport::default(), ImageReport::default(), ImageReport::default(), ImageReport::default()];
+        let refs: Vec<&ImageReport> = reports.iter().collect();
+        let ids = group_ids(&["a/1.jpg", "b/2.jpg", "a/3.jpg", "4.jpg"], &refs, RelativeGroup::Directory, 1.0);
+        assert_eq!(ids, vec![0, 1, 0, 2]);
+    }
+}
@@ -0,0 +1,133 @@
+use image::{imageops, ImageBuffer, Luma};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use crate::blur_result::BlurResult;
+
+/// A region of interest given as `x,y,w,h`, either in pixels or as fractions of the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Roi {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    /// True when all four values are fractions (0.0-1.0) of the frame size.
+    pub fractional: bool,
+}
+
+impl FromStr for Roi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+        if parts.len() != 4 {
+            return Err(format!("Invalid ROI '{}': expected x,y,w,h", s));
+        }
+        let mut vals = [0.0f64; 4];
+        for (v, p) in vals.iter_mut().zip(&parts) {
+            *v = p.parse().map_err(|_| format!("Invalid ROI '{}': '{}' is not a number", s, p))?;
+            if *v < 0.0 {
+                return Err(format!("Invalid ROI '{}': values must not be negative", s));
+            }
+        }
+        // Any decimal point with every value <= 1.0 means fractions; plain integers are pixels
+        let fractional = parts.iter().any(|p| p.contains('.')) && vals.iter().all(|&v| v <= 1.0);
+        if vals[2] == 0.0 || vals[3] == 0.0 {
+            return Err(format!("Invalid ROI '{}': width and height must be positive", s));
+        }
+        Ok(Roi { x: vals[0], y: vals[1], w: vals[2], h: vals[3], fractional })
+    }
+}
+
+impl TryFrom<String> for Roi {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Roi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.w, self.h)
+    }
+}
+
+/// clap value parser for `--roi`.
+pub fn parse_roi(s: &str) -> Result<Roi, String> {
+    s.parse()
+}
+
+impl Roi {
+    /// Resolves the region to a pixel rectangle (x, y, w, h) clamped to the frame.
+    pub fn to_pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (x, y, w, h) = if self.fractional {
+            (self.x * width as f64, self.y * height as f64, self.w * width as f64, self.h * height as f64)
+        } else {
+            (self.x, self.y, self.w, self.h)
+        };
+        let x = (x.round() as u32).min(width.saturating_sub(1));
+        let y = (y.round() as u32).min(height.saturating_sub(1));
+        let w = (w.round() as u32).clamp(1, (width - x).max(1));
+        let h = (h.round() as u32).clamp(1, (height - y).max(1));
+        (x, y, w, h)
+    }
+}
+
+/// Detector results for one region of interest.
+pub struct RoiReport {
+    pub roi: Roi,
+    /// Pixel rectangle (x, y, w, h) the region resolved to.
+    pub rect: (u32, u32, u32, u32),
+    pub results: Vec<BlurResult>,
+    /// True if every detector considers the region blurry.
+    pub is_blurry: bool,
+}
+
+/// Crops `img` to the region of interest.
+pub fn crop_roi(img: &ImageBuffer<Luma<u8>, Vec<u8>>, roi: &Roi) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let (x, y, w, h) = roi.to_pixels(img.width(), img.height());
+    imageops::crop_imm(img, x, y, w, h).to_image()
+}
+
+/// Fades detail away from the frame center with a Gaussian falloff.
+///
+/// Each pixel is pulled toward the frame mean by `1 - weight`, so edges (and thus every
+/// gradient-based metric) are attenuated in proportion to their distance from the center.
+/// `sigma` is a fraction of the frame width/height.
+pub fn center_weight(img: &ImageBuffer<Luma<u8>, Vec<u8>>, sigma: f64) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    let n = (width * height).max(1.0);
+    let mean = img.pixels().map(|p| p[0] as f64).sum::<f64>() / n;
+    let sigma = sigma.max(1e-3);
+    let (cx, cy) = ((width - 1.0) / 2.0, (height - 1.0) / 2.0);
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let dx = (x as f64 - cx) / (sigma * width);
+        let dy = (y as f64 - cy) / (sigma * height);
+        let weight = (-(dx * dx + dy * dy) / 2.0).exp();
+        let p = img.get_pixel(x, y)[0] as f64;
+        Luma([(mean + (p - mean) * weight).round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fraction_and_pixels() {
+        let frac: Roi = "0.2,0.2,0.6,0.6".parse().unwrap();
+        assert!(frac.fractional);
+        assert_eq!(frac.to_pixels(1000, 500), (200, 100, 600, 300));
+
+        let px: Roi = "10,20,30,40".parse().unwrap();
+        assert!(!px.fractional);
+        assert_eq!(px.to_pixels(1000, 500), (10, 20, 30, 40));
+        // Clamped to the frame
+        assert_eq!(px.to_pixels(25, 30), (10, 20, 15, 10));
+
+        assert!("1,2,3".parse::<Roi>().is_err());
+        assert!("0,0,0,5".parse::<Roi>().is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt::Write;
+use crate::blur_result::ImageReport;
+
+/// Values one detector produced over a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorScores {
+    pub name: String,
+    pub threshold: f64,
+    pub values: Vec<f64>,
+}
+
+/// Running totals of a stdin batch run, rendered once the input ends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchSummary {
+    pub blurry: usize,
+    pub sharp: usize,
+    /// Images written to stdout
+    pub emitted: usize,
+    /// Paths that could not be analyzed
+    pub errors: usize,
+    /// Input records that were not paths (not UTF-8)
+    pub skipped: usize,
+    pub detectors: Vec<DetectorScores>,
+}
+
+impl BatchSummary {
+    pub fn record(&mut self, report: &ImageReport) {
+        if report.is_blurry {
+            self.blurry += 1;
+        } else {
+            self.sharp += 1;
+        }
+        for r in report.results.iter().filter(|r| r.value.is_finite()) {
+            match self.detectors.iter_mut().find(|d| d.name == r.name) {
+                Some(d) => d.values.push(r.value),
+                None => self.detectors.push(DetectorScores { name: r.name.clone(), threshold: r.threshold, values: vec![r.value] }),
+            }
+        }
+    }
+
+    /// Counts, per-detector statistics and a histogram of each detector's values with `bins` bins.
+    pub fn render(&self, bins: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Summary: {} images ({} blurry, {} sharp), {} emitted, {} decode errors, {} skipped paths",
+            self.blurry + self.sharp, self.blurry, self.sharp, self.emitted, self.errors, self.skipped);
+        for d in &self.detectors {
+            let mut sorted = d.values.clone();
+            sorted.sort_by(f64::total_cmp);
+            let mean = sorted.iter().sum::<f64>() / sorted.len() as f64;
+            let _ = writeln!(out, "{} (threshold {:.3}): min {:.3}, p5 {:.3}, p25 {:.3}, median {:.3}, p75 {:.3}, p95 {:.3}, max {:.3}, mean {:.3}",
+                d.name, d.threshold, sorted[0], percentile(&sorted, 5.0), percentile(&sorted, 25.0), percentile(&sorted, 50.0),
+                percentile(&sorted, 75.0), percentile(&sorted, 95.0), sorted[sorted.len() - 1], mean);
+            out.push_str(&histogram(&sorted, d.threshold, bins.max(1)));
+        }
+        out
+    }
+}
+
+/// Linearly interpolated percentile of sorted, non-empty values.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let pos = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+/// ASCII histogram of sorted values over equal-width bins between min and max. The bin holding
+/// the threshold is marked; a threshold outside the range gets a marker line above or below.
+pub fn histogram(sorted: &[f64], threshold: f64, bins: usize) -> String {
+    const WIDTH: usize = 40;
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    // Identical values all fit one bin
+    let bins = if max > min { bins } else { 1 };
+    let step = (max - min) / bins as f64;
+    let mut counts = vec![0usize; bins];
+    for &v in sorted {
+        let bin = if step > 0.0 { ((v - min) / step) as usize } else { 0 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    let peak = counts.iter().copied().max().unwrap_or(0).max(1);
+    let marker = format!("  {:->12} threshold {:.3}\n", "", threshold);
+    let mut out = String::new();
+    if threshold < min {
+        out.push_str(&marker);
+    }
+    for (i, &count) in counts.iter().enumerate() {
+        let (lo, hi) = (min + step * i as f64, min + step * (i + 1) as f64);
+        let holds_threshold = threshold >= lo && (threshold < hi || (i == bins - 1 && threshold <= max));
+        let _ = writeln!(out, "  {:>12.3} .. {:<12.3} |{:<width$}| {}{}", lo, hi, "#".repeat(count * WIDTH / peak), count,
+            if holds_threshold { " <- threshold" } else { "" }, width = WIDTH);
+    }
+    if threshold > max {
+        out.push_str(&marker);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_interpolate() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert!((percentile(&sorted, 90.0) - 4.6).abs() < 1e-12);
+        assert_eq!(percentile(&[7.0], 95.0), 7.0);
+    }
+
+    #[test]
+    fn histogram_marks_threshold() {
+        let sorted = [0.0, 0.1, 0.5, 0.9, 1.0, 1.0];
+        let text = histogram(&sorted, 0.55, 2);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("| 2"));
+        assert!(lines[1].ends_with("| 4 <- threshold"));
+        assert!(histogram(&sorted, 2.0, 2).lines().last().unwrap().contains("threshold 2.000"));
+    }
+}
@@ -0,0 +1,119 @@
+use image::{imageops, ImageBuffer, Luma};
+use crate::blur_detector::BlurDetector;
+use crate::config::TileConfig;
+
+/// Sharpness of a single tile of the frame, in image pixel coordinates.
+#[derive(Debug, Clone)]
+pub struct TileResult {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub value: f64,
+    pub is_blurry: bool,
+}
+
+/// Per-detector summary of a tiled analysis.
+#[derive(Debug, Clone)]
+pub struct TileSummary {
+    pub name: String,
+    pub grid: u32,
+    pub max_value: f64,
+    pub top_k: usize,
+    pub top_k_mean: f64,
+    /// Fraction of tiles the detector considers sharp.
+    pub sharp_fraction: f64,
+    /// Blurry unless at least `min_sharp_fraction` of the frame is in focus.
+    pub is_blurry: bool,
+    pub tiles: Vec<TileResult>,
+}
+
+/// Splits a `width`x`height` frame into a `grid`x`grid` layout of (x, y, w, h) tiles.
+/// The last row/column absorbs any remainder so the tiles always cover the whole frame.
+pub fn tile_bounds(width: u32, height: u32, grid: u32) -> Vec<(u32, u32, u32, u32)> {
+    let grid = effective_grid(width, height, grid);
+    let tile_w = width / grid;
+    let tile_h = height / grid;
+    let mut bounds = Vec::with_capacity((grid * grid) as usize);
+    for row in 0..grid {
+        for col in 0..grid {
+            let x = col * tile_w;
+            let y = row * tile_h;
+            let w = if col == grid - 1 { width - x } else { tile_w };
+            let h = if row == grid - 1 { height - y } else { tile_h };
+            bounds.push((x, y, w, h));
+        }
+    }
+    bounds
+}
+
+/// Clamps the requested grid so that every tile is at least one pixel wide and tall.
+pub fn effective_grid(width: u32, height: u32, grid: u32) -> u32 {
+    grid.max(1).min(width.max(1)).min(height.max(1))
+}
+
+/// Runs `det` on every tile of `img` and summarizes how much of the frame is sharp.
+pub fn analyze_tiles(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    det: &dyn BlurDetector,
+    config: &TileConfig,
+) -> TileSummary {
+    let grid = config.grid.unwrap_or(4);
+    let top_k = config.top_k.unwrap_or(3).max(1);
+    let min_sharp_fraction = config.min_sharp_fraction.unwrap_or(0.1);
+
+    let mut tiles = Vec::new();
+    for (x, y, w, h) in tile_bounds(img.width(), img.height(), grid) {
+        let tile = imageops::crop_imm(img, x, y, w, h).to_image();
+        let (value, is_blurry) = det.detect(&tile);
+        tiles.push(TileResult { x, y, width: w, height: h, value, is_blurry });
+    }
+
+    let mut values: Vec<f64> = tiles.iter().map(|t| t.value).collect();
+    values.sort_by(|a, b| b.total_cmp(a));
+    let max_value = values.first().copied().unwrap_or(0.0);
+    let k = top_k.min(values.len()).max(1);
+    let top_k_mean = values.iter().take(k).sum::<f64>() / k as f64;
+    let sharp_tiles = tiles.iter().filter(|t| !t.is_blurry).count();
+    let sharp_fraction = if tiles.is_empty() { 0.0 } else { sharp_tiles as f64 / tiles.len() as f64 };
+    let is_blurry = sharp_tiles == 0 || sharp_fraction < min_sharp_fraction;
+
+    TileSummary {
+        name: det.name().to_string(),
+        grid: effective_grid(img.width(), img.height(), grid),
+        max_value,
+        top_k: k,
+        top_k_mean,
+        sharp_fraction,
+        is_blurry,
+        tiles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blur_laplacian::LaplacianVarianceDetector;
+
+    #[test]
+    fn test_tiles_cover_frame() {
+        let bounds = tile_bounds(103, 50, 4);
+        assert_eq!(bounds.len(), 16);
+        let area: u32 = bounds.iter().map(|&(_, _, w, h)| w * h).sum();
+        assert_eq!(area, 103 * 50);
+    }
+
+    #[test]
+    fn test_sharp_subject_on_flat_background() {
+        // Flat grey frame with a checkerboard "subject" in one corner tile
+        let img: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(100, 100, |x, y| {
+            if x < 25 && y < 25 && (x + y) % 2 == 0 { Luma([255]) } else { Luma([128]) }
+        });
+        let det = LaplacianVarianceDetector::new(0.1);
+        let config = TileConfig { grid: Some(4), top_k: Some(1), min_sharp_fraction: Some(0.05) };
+        let summary = analyze_tiles(&img, &det, &config);
+        assert!(!summary.is_blurry, "A single sharp tile should make the image sharp");
+        assert!((summary.sharp_fraction - 1.0 / 16.0).abs() < 1e-9);
+        assert_eq!(summary.max_value, summary.top_k_mean);
+    }
+}
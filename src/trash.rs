@@ -0,0 +1,230 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+/// Where a file went: its new path in `files/` and the `.trashinfo` describing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trashed {
+    pub file: PathBuf,
+    pub info: PathBuf,
+}
+
+/// Moves `path` to the trash per the freedesktop.org Trash specification, so any desktop's file
+/// manager can restore it: to the home trash if it lives on the same filesystem, otherwise to the
+/// trash at the top of its own filesystem (`$topdir/.Trash/$uid`, else `$topdir/.Trash-$uid`).
+#[cfg(unix)]
+pub fn trash(path: &Path) -> Result<Trashed, String> {
+    let abs = absolute(path)?;
+    let dev = fs::symlink_metadata(&abs).map_err(|e| format!("Failed to stat {}: {}", abs.display(), e))?.dev();
+    let home = home_trash()?;
+    let home_dev = fs::create_dir_all(&home).ok().and_then(|_| fs::metadata(&home).ok()).map(|m| m.dev());
+    if home_dev == Some(dev) {
+        return trash_into(&abs, &home, &abs);
+    }
+    let top = topdir(&abs, dev);
+    let trash_dir = topdir_trash(&top).ok_or_else(|| format!("No usable trash directory for {} under {}", abs.display(), top.display()))?;
+    // Topdir trashes record paths relative to the top directory
+    let recorded = abs.strip_prefix(&top).map(Path::to_path_buf).unwrap_or_else(|_| abs.clone());
+    trash_into(&abs, &trash_dir, &recorded)
+}
+
+#[cfg(not(unix))]
+pub fn trash(path: &Path) -> Result<Trashed, String> {
+    Err(format!("Cannot trash {}: the trash is only supported on Unix-like systems", path.display()))
+}
+
+/// Restores a trashed file to `to` and removes its `.trashinfo`.
+pub fn restore(trashed: &Trashed, to: &Path) -> Result<(), String> {
+    fs::rename(&trashed.file, to).map_err(|e| format!("Failed to restore {} to {}: {}", trashed.file.display(), to.display(), e))?;
+    fs::remove_file(&trashed.info).ok();
+    Ok(())
+}
+
+/// The `.trashinfo` of a file in a trash's `files/` directory.
+pub fn info_path(trashed_file: &Path) -> PathBuf {
+    let name = trashed_file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let trash_dir = trashed_file.parent().and_then(Path::parent).unwrap_or(Path::new(""));
+    trash_dir.join("info").join(format!("{}.trashinfo", name))
+}
+
+#[cfg(unix)]
+fn home_trash() -> Result<PathBuf, String> {
+    match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(data) => Ok(PathBuf::from(data).join("Trash")),
+        None => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local/share/Trash"))
+            .ok_or_else(|| "Neither XDG_DATA_HOME nor HOME is set; cannot find the trash".to_string()),
+    }
+}
+
+#[cfg(unix)]
+/// Highest ancestor of `path` on device `dev`: its mount point.
+fn topdir(path: &Path, dev: u64) -> PathBuf {
+    let mut top = path.to_path_buf();
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent).ok().map(|m| m.dev()) != Some(dev) {
+            break;
+        }
+        top = parent.to_path_buf();
+    }
+    top
+}
+
+#[cfg(unix)]
+fn topdir_trash(top: &Path) -> Option<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    // An admin-provided .Trash must be a real, sticky directory
+    let shared = top.join(".Trash");
+    if let Ok(meta) = fs::symlink_metadata(&shared) {
+        if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+            let dir = shared.join(uid.to_string());
+            if fs::create_dir_all(&dir).is_ok() {
+                return Some(dir);
+            }
+        }
+    }
+    let own = top.join(format!(".Trash-{}", uid));
+    match fs::symlink_metadata(&own) {
+        Ok(meta) if meta.is_dir() => Some(own),
+        Ok(_) => None,
+        Err(_) => {
+            fs::create_dir(&own).ok()?;
+            fs::set_permissions(&own, fs::Permissions::from_mode(0o700)).ok()?;
+            Some(own)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn trash_into(abs: &Path, trash_dir: &Path, recorded: &Path) -> Result<Trashed, String> {
+    let files = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    for dir in [&files, &info_dir] {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let name = abs.file_name().ok_or_else(|| format!("Cannot trash {}", abs.display()))?.to_string_lossy().into_owned();
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem.to_string(), format!(".{}", ext)),
+        _ => (name.clone(), String::new()),
+    };
+    let body = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", percent_encode(recorded), local_timestamp());
+    // Claiming the .trashinfo name atomically reserves the name in files/ too
+    for n in 0..10_000 {
+        let candidate = if n == 0 { name.clone() } else { format!("{}.{}{}", stem, n, ext) };
+        let info = info_dir.join(format!("{}.trashinfo", candidate));
+        let mut f = match OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create {}: {}", info.display(), e)),
+        };
+        let file = files.join(&candidate);
+        if file.exists() {
+            fs::remove_file(&info).ok();
+            continue;
+        }
+        let written = f.write_all(body.as_bytes()).and_then(|_| f.sync_all());
+        if let Err(e) = written.and_then(|_| fs::rename(abs, &file)) {
+            fs::remove_file(&info).ok();
+            return Err(format!("Failed to move {} to the trash: {}", abs.display(), e));
+        }
+        return Ok(Trashed { file, info });
+    }
+    Err(format!("Too many files named {} in the trash", name))
+}
+
+#[cfg(unix)]
+fn absolute(path: &Path) -> Result<PathBuf, String> {
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map_err(|e| e.to_string())?.join(path)
+    };
+    // Lexically, so a symlink is trashed itself rather than its target
+    let mut abs = PathBuf::new();
+    for c in joined.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                abs.pop();
+            }
+            c => abs.push(c),
+        }
+    }
+    Ok(abs)
+}
+
+#[cfg(unix)]
+/// URL-style escaping of a path, as the spec requires for `Path=`.
+fn percent_encode(path: &Path) -> String {
+    let mut out = String::new();
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Current local time as `YYYY-MM-DDThh:mm:ss`.
+#[cfg(unix)]
+pub fn local_timestamp() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return "1970-01-01T00:00:00".to_string();
+    }
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+/// Current UTC time as `YYYY-MM-DDThh:mm:ss`; without `localtime_r` the local offset is unknown.
+#[cfg(not(unix))]
+pub fn local_timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()) as i64;
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trashes_and_restores() {
+        let root = std::env::temp_dir().join(format!("grepfuzz-trash-{}", std::process::id()));
+        let trash_dir = root.join("Trash");
+        let photo = root.join("my photo.jpg");
+        fs::create_dir_all(&root).unwrap();
+        for _ in 0..2 {
+            fs::write(&photo, b"pixels").unwrap();
+            trash_into(&photo, &trash_dir, &photo).unwrap();
+        }
+        // The second copy gets a fresh name instead of overwriting the first
+        let second = trash_dir.join("files/my photo.1.jpg");
+        assert!(trash_dir.join("files/my photo.jpg").exists() && second.exists() && !photo.exists());
+        let info = fs::read_to_string(info_path(&second)).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=") && info.contains("my%20photo.jpg\nDeletionDate="));
+
+        restore(&Trashed { file: second.clone(), info: info_path(&second) }, &photo).unwrap();
+        assert!(photo.exists() && !info_path(&second).exists());
+        fs::remove_dir_all(&root).ok();
+    }
+}
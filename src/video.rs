@@ -0,0 +1,126 @@
+use image::RgbImage;
+use opencv::{core, prelude::*, videoio};
+use std::path::Path;
+
+/// Video extensions sampled frame by frame instead of decoded as a still image.
+pub const VIDEO_EXTENSIONS: [&str; 10] = ["mp4", "mov", "m4v", "mkv", "avi", "webm", "mts", "m2ts", "3gp", "wmv"];
+
+pub fn is_video_path(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Which frames of a video are analyzed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Every Nth frame, starting with the first
+    EveryFrames(u64),
+    /// The first frame at or after each multiple of N seconds
+    EverySeconds(f64),
+}
+
+/// Decides frame by frame whether to sample, so the decoder only has to retrieve chosen frames.
+pub struct Sampler {
+    sampling: Sampling,
+    next_time: f64,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling) -> Self {
+        Self { sampling, next_time: 0.0 }
+    }
+
+    pub fn should_sample(&mut self, index: u64, timestamp: f64) -> bool {
+        match self.sampling {
+            Sampling::EveryFrames(n) => index.is_multiple_of(n.max(1)),
+            Sampling::EverySeconds(seconds) => {
+                if timestamp + 1e-9 < self.next_time {
+                    return false;
+                }
+                // Skip whole intervals a long gap (or a slow stream) jumped over
+                let seconds = seconds.max(1e-3);
+                self.next_time = ((timestamp / seconds).floor() + 1.0) * seconds;
+                true
+            }
+        }
+    }
+}
+
+/// Stream properties as reported by the container.
+pub struct VideoInfo {
+    pub fps: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Frames decoded, sampled or not.
+    pub frames_read: u64,
+}
+
+/// Decodes `path` with OpenCV and calls `visit(index, timestamp_seconds, frame)` for each sampled frame.
+///
+/// Every frame is grabbed, so sampling does not depend on the backend's seeking accuracy, but only
+/// sampled frames are converted. Timestamps come from the frame rate, or from the stream position
+/// when the container does not report one.
+pub fn sample_frames<F>(path: &Path, sampling: Sampling, mut visit: F) -> Result<VideoInfo, String>
+where
+    F: FnMut(u64, f64, RgbImage),
+{
+    let err = |e: &dyn std::fmt::Display| format!("Failed to read video {}: {}", path.display(), e);
+    let mut capture = videoio::VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).map_err(|e| err(&e))?;
+    if !capture.is_opened().map_err(|e| err(&e))? {
+        return Err(err(&"unsupported or unreadable video"));
+    }
+    let fps = capture.get(videoio::CAP_PROP_FPS).unwrap_or(0.0);
+    let mut info = VideoInfo { fps, width: 0, height: 0, frames_read: 0 };
+    let mut sampler = Sampler::new(sampling);
+    let mut mat = core::Mat::default();
+    while capture.grab().map_err(|e| err(&e))? {
+        let index = info.frames_read;
+        info.frames_read += 1;
+        let timestamp = if fps > 0.0 {
+            index as f64 / fps
+        } else {
+            capture.get(videoio::CAP_PROP_POS_MSEC).unwrap_or(0.0) / 1000.0
+        };
+        if !sampler.should_sample(index, timestamp) {
+            continue;
+        }
+        if !capture.retrieve(&mut mat, 0).map_err(|e| err(&e))? || mat.empty() {
+            continue;
+        }
+        let frame = mat_to_rgb(&mat).map_err(|e| err(&e))?;
+        info.width = frame.width();
+        info.height = frame.height();
+        visit(index, timestamp, frame);
+    }
+    Ok(info)
+}
+
+/// Converts an 8-bit BGR (or gray) frame to RGB.
+fn mat_to_rgb(mat: &core::Mat) -> Result<RgbImage, String> {
+    let (width, height) = (mat.cols() as u32, mat.rows() as u32);
+    let bytes = mat.data_bytes().map_err(|e| e.to_string())?;
+    let rgb = match mat.channels() {
+        3 => bytes.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0]]).collect(),
+        1 => bytes.iter().flat_map(|&v| [v, v, v]).collect(),
+        n => return Err(format!("unsupported {}-channel frame", n)),
+    };
+    RgbImage::from_raw(width, height, rgb).ok_or_else(|| "frame size mismatch".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_by_frames_and_seconds() {
+        let mut by_frames = Sampler::new(Sampling::EveryFrames(10));
+        let picked: Vec<u64> = (0..35).filter(|&i| by_frames.should_sample(i, i as f64 / 30.0)).collect();
+        assert_eq!(picked, vec![0, 10, 20, 30]);
+
+        // 30 fps with one sample every 0.5 s
+        let mut by_seconds = Sampler::new(Sampling::EverySeconds(0.5));
+        let picked: Vec<u64> = (0..70).filter(|&i| by_seconds.should_sample(i, i as f64 / 30.0)).collect();
+        assert_eq!(picked, vec![0, 15, 30, 45, 60]);
+    }
+}
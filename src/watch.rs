@@ -0,0 +1,203 @@
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+use crate::actions::normalize;
+
+/// How often the watch loop checks for settled files and the stop flag.
+const TICK: Duration = Duration::from_millis(100);
+
+pub struct WatchOptions {
+    pub recursive: bool,
+    /// Quiet period after a file's last event before it is checked
+    pub debounce: Duration,
+    /// Analyze the images already in the directory at startup
+    pub backlog: bool,
+    /// Directories whose files are never analyzed, such as action destinations inside the watched tree
+    pub ignore: Vec<PathBuf>,
+}
+
+/// Tracks files that are still being written. A file is settled once it has had no events for the
+/// debounce period and was either closed after writing or kept the same non-zero size over a
+/// further debounce period (for writers, like network shares, that never report a close).
+pub struct Settler {
+    debounce: Duration,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+struct Pending {
+    last_event: Instant,
+    size: Option<u64>,
+    closed: bool,
+}
+
+impl Settler {
+    pub fn new(debounce: Duration) -> Self {
+        Self { debounce, pending: HashMap::new() }
+    }
+
+    /// Records an event on `path`; `closed` is a close after writing, anything else restarts the wait.
+    pub fn touch(&mut self, path: &Path, closed: bool, now: Instant) {
+        let entry = self.pending.entry(path.to_path_buf()).or_insert(Pending { last_event: now, size: None, closed: false });
+        entry.last_event = now;
+        entry.closed = closed;
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.pending.remove(path);
+    }
+
+    /// Files that finished writing, in path order. Files that vanished are dropped.
+    pub fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        self.pending.retain(|path, p| {
+            if now.duration_since(p.last_event) < self.debounce {
+                return true;
+            }
+            let Ok(meta) = fs::metadata(path) else { return false };
+            if p.closed || (p.size == Some(meta.len()) && meta.len() > 0) {
+                ready.push(path.clone());
+                return false;
+            }
+            p.size = Some(meta.len());
+            p.last_event = now;
+            true
+        });
+        ready.sort();
+        ready
+    }
+}
+
+/// Sets a flag on SIGINT, SIGTERM or SIGHUP, so the watch loop finishes the current image and returns.
+pub fn stop_on_signal() -> Result<Arc<AtomicBool>, String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))
+        .map_err(|e| format!("Failed to install signal handler: {}", e))?;
+    Ok(stop)
+}
+
+/// Watches `dir` and calls `on_ready` with every image that lands in it, once it finished writing,
+/// until `stop` is set. Images are analyzed again only if they change after being handled.
+pub fn watch<F: FnMut(&Path)>(dir: &Path, options: &WatchOptions, stop: &AtomicBool, mut on_ready: F) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| format!("Failed to start file watcher: {}", e))?;
+    let mode = if options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    // Watch before scanning, so a file landing during the scan is not missed
+    watcher.watch(dir, mode).map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+
+    let cwd = std::env::current_dir().map_err(|e| format!("Failed to read the current directory: {}", e))?;
+    let ignore: Vec<PathBuf> = options.ignore.iter().map(|p| normalize(&cwd.join(p))).collect();
+    let wanted = |path: &Path| is_image(path) && !ignore.iter().any(|i| normalize(&cwd.join(path)).starts_with(i));
+    let mut settler = Settler::new(options.debounce);
+    if options.backlog {
+        let now = Instant::now();
+        for path in scan(dir, options.recursive)?.into_iter().filter(|p| wanted(p)) {
+            settler.touch(&path, false, now);
+        }
+    }
+
+    let mut handled: HashMap<PathBuf, (u64, Option<SystemTime>)> = HashMap::new();
+    while !stop.load(Ordering::SeqCst) {
+        match rx.recv_timeout(TICK) {
+            Ok(Ok(event)) => {
+                let now = Instant::now();
+                match event.kind {
+                    EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                        event.paths.iter().for_each(|p| settler.forget(p));
+                    }
+                    // A rename within the directory reports both names; the file lives on under the last
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                        if let Some(from) = event.paths.first() {
+                            settler.forget(from);
+                        }
+                        if let Some(to) = event.paths.last().filter(|p| wanted(p)) {
+                            settler.touch(to, true, now);
+                        }
+                    }
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                        let closed = matches!(event.kind, EventKind::Access(_) | EventKind::Modify(ModifyKind::Name(_)));
+                        for path in event.paths.iter().filter(|p| wanted(p)) {
+                            settler.touch(path, closed, now);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Err(e)) => eprintln!("File watcher error: {}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err("File watcher stopped unexpectedly".to_string()),
+        }
+        for path in settler.settled(Instant::now()) {
+            let Ok(meta) = fs::metadata(&path) else { continue };
+            let stamp = (meta.len(), meta.modified().ok());
+            if handled.insert(path.clone(), stamp) == Some(stamp) {
+                continue;
+            }
+            on_ready(&path);
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stills, camera RAWs and videos, by extension; hidden files (often partial uploads) are skipped.
+fn is_image(path: &Path) -> bool {
+    let hidden = path.file_name().is_none_or(|n| n.to_string_lossy().starts_with('.'));
+    !hidden && (image::ImageFormat::from_path(path).is_ok() || crate::raw_preview::is_raw_path(path) || crate::video::is_video_path(path))
+}
+
+fn scan(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    let mut files = Vec::new();
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                files.extend(scan(&path, true)?);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_writes_to_settle() {
+        let dir = std::env::temp_dir().join(format!("grepfuzz-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (closed, copied) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&closed, b"jpeg").unwrap();
+        fs::write(&copied, b"jp").unwrap();
+        let debounce = Duration::from_millis(500);
+        let mut settler = Settler::new(debounce);
+        let start = Instant::now();
+        settler.touch(&closed, true, start);
+        settler.touch(&copied, false, start);
+        assert!(settler.settled(start + debounce / 2).is_empty());
+        // Closed files are ready after the quiet period; the other one needs its size confirmed
+        assert_eq!(settler.settled(start + debounce), vec![closed.clone()]);
+        fs::write(&copied, b"jpeg").unwrap();
+        assert!(settler.settled(start + debounce * 2).is_empty());
+        assert_eq!(settler.settled(start + debounce * 3), vec![copied.clone()]);
+
+        settler.touch(&closed, false, start);
+        fs::remove_file(&closed).unwrap();
+        assert!(settler.settled(start + debounce * 4).is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,305 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use crate::blur_result::ImageReport;
+use crate::config::XmpConfig;
+use crate::detector_helpers::detector_key;
+use crate::html_report::escape_html;
+
+pub const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+pub const GREPFUZZ_NS: &str = "https://github.com/microuser/grepfuzz/ns/1.0/";
+
+static ATTR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"([\w:.-]+)\s*=\s*("[^"]*"|'[^']*')"#).expect("valid regex"));
+static NAMESPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"xmlns:([\w.-]+)\s*=\s*["']([^"']*)["']"#).expect("valid regex"));
+
+/// How a new sidecar is named. An existing sidecar under either name is always the one updated.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SidecarNaming {
+    /// `IMG_0001.CR2.xmp` (darktable, digiKam)
+    Append,
+    /// `IMG_0001.xmp` (Lightroom, Capture One)
+    Replace,
+}
+
+/// Properties grepfuzz sets in a sidecar. The rating and label written are recorded in the grepfuzz
+/// namespace; `None` removes the one an earlier run wrote, unless it was changed since, and otherwise
+/// leaves the existing value alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmpUpdate {
+    pub rating: Option<i32>,
+    pub label: Option<String>,
+    /// Attributes in the grepfuzz namespace; any others from an earlier run are removed.
+    pub grepfuzz: Vec<(String, String)>,
+}
+
+impl XmpUpdate {
+    /// Rating and label for the verdict (configurable, by default 1 star and Red for rejects and
+    /// none for keepers, which takes back an earlier reject's), plus the verdict and every detector value in the grepfuzz namespace.
+    pub fn for_report(report: &ImageReport, rejected: bool, config: &XmpConfig) -> Self {
+        let (rating, label) = if rejected {
+            (Some(config.blurry_rating.unwrap_or(1)), Some(config.blurry_label.clone().unwrap_or_else(|| "Red".to_string())))
+        } else {
+            (config.sharp_rating, config.sharp_label.clone())
+        };
+        let mut grepfuzz = vec![("verdict".to_string(), if rejected { "blurry" } else { "sharp" }.to_string())];
+        grepfuzz.extend(report.results.iter().map(|r| (detector_key(&r.name), r.value.to_string())));
+        if let Some(p) = report.blur_probability {
+            grepfuzz.push(("blur_probability".to_string(), p.to_string()));
+        }
+        Self { rating, label: label.filter(|l| !l.is_empty()), grepfuzz }
+    }
+}
+
+/// The sidecar of `image`: whichever of `IMG.ext.xmp` and `IMG.xmp` exists, else the one `naming` picks.
+pub fn sidecar_path(image: &Path, naming: SidecarNaming) -> PathBuf {
+    let mut appended = image.as_os_str().to_owned();
+    appended.push(".xmp");
+    let appended = PathBuf::from(appended);
+    let replaced = image.with_extension("xmp");
+    if appended.exists() {
+        appended
+    } else if replaced.exists() && replaced != image {
+        replaced
+    } else {
+        match naming {
+            SidecarNaming::Append => appended,
+            SidecarNaming::Replace => replaced,
+        }
+    }
+}
+
+/// Applies `update` to a sidecar's text, or to a fresh packet if there is none. Everything else
+/// in the sidecar is kept as written; only the first `rdf:Description` element is rewritten.
+pub fn apply_update(existing: Option<&str>, update: &XmpUpdate) -> Result<String, String> {
+    let mut text = match existing {
+        Some(text) => text.to_string(),
+        None => EMPTY_PACKET.to_string(),
+    };
+    if !text.contains("<rdf:Description") {
+        let end = text.find("</rdf:RDF>").ok_or("Sidecar has no rdf:RDF element")?;
+        text.insert_str(end, "<rdf:Description rdf:about=\"\"/>\n ");
+    }
+    let xmp = namespace_prefix(&text, XMP_NS);
+    let ours = namespace_prefix(&text, GREPFUZZ_NS);
+    let start = text.find("<rdf:Description").expect("checked above");
+    let (body_end, self_closing) = tag_end(&text, start).ok_or("Unterminated rdf:Description tag in sidecar")?;
+
+    let body = &text[start + "<rdf:Description".len()..body_end];
+    let mut attrs: Vec<(String, String)> = ATTR_RE.captures_iter(body)
+        .map(|c| (c[1].to_string(), c[2][1..c[2].len() - 1].to_string()))
+        .collect();
+    let xmp_prefix = xmp.clone().unwrap_or_else(|| "xmp".to_string());
+    let our_prefix = ours.clone().unwrap_or_else(|| "grepfuzz".to_string());
+    if xmp.is_none() {
+        attrs.push(("xmlns:xmp".to_string(), XMP_NS.to_string()));
+    }
+    if ours.is_none() {
+        attrs.push(("xmlns:grepfuzz".to_string(), GREPFUZZ_NS.to_string()));
+    }
+
+    // Rating and label may also be written as child elements of this description; update those in place
+    let children_start = body_end + if self_closing { 2 } else { 1 };
+    let children_end = if self_closing {
+        children_start
+    } else {
+        description_close(&text, children_start).ok_or("Unterminated rdf:Description element in sidecar")?
+    };
+    let mut children = text[children_start..children_end].to_string();
+    let mut written = Vec::new();
+    for (property, value) in [("Rating", update.rating.map(|r| r.to_string())), ("Label", update.label.clone())] {
+        let name = format!("{}:{}", xmp_prefix, property);
+        let record = property.to_lowercase();
+        let element = find_element(&children, &name);
+        match value.map(|v| escape_html(&v)) {
+            Some(value) => {
+                match element {
+                    Some((_, content)) => children.replace_range(content, &value),
+                    None => match attrs.iter_mut().find(|(n, _)| *n == name) {
+                        Some((_, v)) => v.clone_from(&value),
+                        None => attrs.push((name, value.clone())),
+                    },
+                }
+                written.push((record, value));
+            }
+            None => {
+                let our_name = format!("{}:{}", our_prefix, record);
+                let Some(recorded) = attrs.iter().find(|(n, _)| *n == our_name).map(|(_, v)| v.clone()) else { continue };
+                match element {
+                    Some((whole, content)) if children[content.clone()] == recorded => {
+                        let line_start = children[..whole.start].trim_end().len();
+                        children.replace_range(line_start..whole.end, "");
+                    }
+                    Some(_) => {}
+                    None => attrs.retain(|(n, v)| !(*n == name && *v == recorded)),
+                }
+            }
+        }
+    }
+    attrs.retain(|(name, _)| !name.starts_with(&format!("{}:", our_prefix)));
+    attrs.extend(written.into_iter().map(|(key, value)| (format!("{}:{}", our_prefix, key), value)));
+    attrs.extend(update.grepfuzz.iter().map(|(key, value)| (format!("{}:{}", our_prefix, key), escape_html(value))));
+
+    let mut tag = String::from("<rdf:Description");
+    for (name, value) in &attrs {
+        tag.push_str(&format!("\n    {}=\"{}\"", name, value.replace('"', "&quot;")));
+    }
+    tag.push_str(if self_closing { "/>" } else { ">" });
+    Ok(format!("{}{}{}{}", &text[..start], tag, children, &text[children_end..]))
+}
+
+/// Writes (or updates) the sidecar of `image` for `report`. The new content goes to a temporary
+/// file first and replaces the sidecar in one rename.
+pub fn write_sidecar(image: &Path, report: &ImageReport, rejected: bool, config: &XmpConfig) -> Result<PathBuf, String> {
+    let path = sidecar_path(image, config.naming.unwrap_or(SidecarNaming::Append));
+    let existing = match fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read sidecar {}: {}", path.display(), e)),
+    };
+    let text = apply_update(existing.as_deref(), &XmpUpdate::for_report(report, rejected, config))
+        .map_err(|e| format!("{}: {}", e, path.display()))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".grepfuzz-tmp");
+    fs::write(&tmp, text)
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to write sidecar {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+const EMPTY_PACKET: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\"/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>
+";
+
+/// Prefix the document binds to `uri`, if any.
+fn namespace_prefix(text: &str, uri: &str) -> Option<String> {
+    NAMESPACE_RE.captures_iter(text).find(|c| &c[2] == uri).map(|c| c[1].to_string())
+}
+
+/// Byte offset of the `</rdf:Description>` closing the element whose children start at `from`.
+fn description_close(text: &str, from: usize) -> Option<usize> {
+    let (mut pos, mut depth) = (from, 0);
+    loop {
+        let close = pos + text[pos..].find("</rdf:Description>")?;
+        match text[pos..close].find("<rdf:Description").map(|i| pos + i) {
+            Some(open) => {
+                let (end, self_closing) = tag_end(text, open)?;
+                depth += usize::from(!self_closing);
+                pos = end + 1;
+            }
+            None if depth == 0 => return Some(close),
+            None => {
+                depth -= 1;
+                pos = close + "</rdf:Description>".len();
+            }
+        }
+    }
+}
+
+/// Span of the first `<name>text</name>` element in `text` and of its text.
+fn find_element(text: &str, name: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let start = text.find(&open)?;
+    let content_start = start + open.len();
+    let content_end = content_start + text[content_start..].find(&close)?;
+    if text[content_start..content_end].contains('<') {
+        return None;
+    }
+    Some((start..content_end + close.len(), content_start..content_end))
+}
+
+/// Byte offset of the `>` or `/>` closing the tag at `start`, skipping quoted values, and whether
+/// the tag is self-closing.
+fn tag_end(text: &str, start: usize) -> Option<(usize, bool)> {
+    let bytes = text.as_bytes();
+    let mut quote = None;
+    for i in start..bytes.len() {
+        match (quote, bytes[i]) {
+            (Some(q), b) if b == q => quote = None,
+            (Some(_), _) => {}
+            (None, b @ (b'"' | b'\'')) => quote = Some(b),
+            (None, b'>') if bytes[i - 1] == b'/' => return Some((i - 1, true)),
+            (None, b'>') => return Some((i, false)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(rating: i32, verdict: &str) -> XmpUpdate {
+        XmpUpdate {
+            rating: Some(rating),
+            label: Some("Red".to_string()),
+            grepfuzz: vec![("verdict".to_string(), verdict.to_string()), ("laplacian".to_string(), "0.1".to_string())],
+        }
+    }
+
+    #[test]
+    fn creates_a_packet() {
+        let text = apply_update(None, &update(1, "blurry")).unwrap();
+        assert!(text.starts_with("<?xpacket begin="));
+        assert!(text.contains("xmp:Rating=\"1\"") && text.contains("xmp:Label=\"Red\""));
+        assert!(text.contains(&format!("xmlns:grepfuzz=\"{}\"", GREPFUZZ_NS)));
+        assert!(text.contains("grepfuzz:laplacian=\"0.1\"/>"));
+    }
+
+    #[test]
+    fn keeps_existing_content() {
+        let existing = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xap="http://ns.adobe.com/xap/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:darktable="http://darktable.sf.net/" darktable:history_end="3" title='a > b'>
+   <xap:Rating>5</xap:Rating>
+   <dc:subject><rdf:Bag><rdf:li>holiday</rdf:li></rdf:Bag></dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+        let first = apply_update(Some(existing), &update(1, "blurry")).unwrap();
+        assert!(first.contains("<xap:Rating>1</xap:Rating>") && !first.contains("xap:Rating=\""));
+        assert!(first.contains("xap:Label=\"Red\""));
+        assert!(first.contains("darktable:history_end=\"3\"") && first.contains("title=\"a > b\""));
+        assert!(first.contains("<rdf:li>holiday</rdf:li>"));
+        // A second run replaces grepfuzz's own attributes instead of piling up
+        let second = apply_update(Some(&first), &update(2, "sharp")).unwrap();
+        assert_eq!(second.matches("grepfuzz:verdict").count(), 1);
+        assert_eq!(second.matches("xmlns:grepfuzz").count(), 1);
+        assert!(second.contains("grepfuzz:verdict=\"sharp\"") && second.contains("<xap:Rating>2</xap:Rating>"));
+    }
+
+    #[test]
+    fn keepers_lose_the_rejection_grepfuzz_wrote() {
+        let keep = XmpUpdate { rating: None, label: None, grepfuzz: vec![("verdict".to_string(), "sharp".to_string())] };
+        let rejected = apply_update(None, &update(1, "blurry")).unwrap();
+        assert!(rejected.contains("grepfuzz:rating=\"1\"") && rejected.contains("grepfuzz:label=\"Red\""));
+        let kept = apply_update(Some(&rejected), &keep).unwrap();
+        assert!(!kept.contains("Rating") && !kept.contains("Label") && !kept.contains("grepfuzz:rating"));
+
+        // A rating given by hand after the reject stays, as does anything outside the first description
+        let existing = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+   <xmp:Rating>1</xmp:Rating>
+   <xmp:Label>Red</xmp:Label>
+   <dc:creator><rdf:Seq><rdf:li><rdf:Description><xmp:Label>Red</xmp:Label></rdf:Description></rdf:li></rdf:Seq></dc:creator>
+  </rdf:Description>
+  <rdf:Description rdf:about=""><xmp:Rating>1</xmp:Rating></rdf:Description>
+ </rdf:RDF>"#;
+        let rejected = apply_update(Some(existing), &update(1, "blurry")).unwrap();
+        let retouched = rejected.replacen("<xmp:Rating>1</xmp:Rating>", "<xmp:Rating>4</xmp:Rating>", 1);
+        let kept = apply_update(Some(&retouched), &keep).unwrap();
+        assert!(kept.contains("<xmp:Rating>4</xmp:Rating>\n   <dc:creator>"));
+        assert_eq!(kept.matches("<xmp:Label>Red</xmp:Label>").count(), 1);
+        assert!(kept.contains("<rdf:Description rdf:about=\"\"><xmp:Rating>1</xmp:Rating></rdf:Description>"));
+    }
+}